@@ -1,141 +1,345 @@
 //! Code analysis and show-code extraction.
 
 use std::collections::HashMap;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::cli::{Annotate, AnalyzeFormat, AnalyzeSort};
+use crate::complexity::{self, FunctionMetrics};
+use crate::content::{self, Content};
+use crate::lang::Language;
+use crate::lexer::{self, LineKind, Syntax};
+use crate::output;
+use crate::todos::Todo;
+use crate::tree::{Node, NodeKind};
+
+/// Files of no detected language are grouped under this name.
+const OTHER: &str = "Other";
 
 #[derive(Debug, Default)]
 pub struct CodeStats {
     pub total_lines: usize,
     pub total_files: usize,
-    pub files_by_extension: HashMap<String, usize>,
-    pub lines_by_extension: HashMap<String, usize>,
+    /// Keyed by language display name.
+    pub languages: HashMap<String, LanguageStats>,
     pub blank_lines: usize,
     pub comment_lines: usize,
+    /// Doc comments and docstrings, not included in `comment_lines`.
+    pub doc_lines: usize,
     pub code_lines: usize,
     // Approximate declaration counts
     pub int_count: usize,
     pub float_count: usize,
     pub string_count: usize,
     pub bool_count: usize,
-    pub function_count: usize,
-    pub class_count: usize,
-}
-
-/// Language comment styles.
-enum CommentStyle {
-    /// Double-slash and block-comment lines
-    CStyle,
-    /// Hash prefix
-    Hash,
-    /// Double-dash
-    DoubleDash,
-    /// Percent
-    Percent,
-    /// Semicolon
-    Semicolon,
-    Unknown,
-}
-
-fn comment_style(ext: &str) -> CommentStyle {
-    match ext {
-        "rs" | "c" | "cpp" | "cc" | "cxx" | "h" | "hpp" | "hxx" | "java" | "kt" | "kts"
-        | "go" | "cs" | "js" | "ts" | "jsx" | "tsx" | "swift" | "dart" | "zig" | "v"
-        | "scala" | "groovy" | "d" | "vala" => CommentStyle::CStyle,
-        "py" | "rb" | "sh" | "bash" | "zsh" | "fish" | "ksh" | "pl" | "pm" | "yaml" | "yml"
-        | "toml" | "r" | "rmd" | "cr" | "nim" | "elixir" | "ex" | "exs" | "makefile"
-        | "conf" | "cfg" | "ini" => CommentStyle::Hash,
-        "lua" | "hs" | "lhs" | "sql" | "ada" => CommentStyle::DoubleDash,
-        "m" | "matlab" | "tex" | "latex" => CommentStyle::Percent,
-        "lisp" | "clj" | "cljs" | "el" | "scm" | "ss" => CommentStyle::Semicolon,
-        _ => CommentStyle::Unknown,
-    }
-}
-
-fn is_comment_line(trimmed: &str, ext: &str) -> bool {
-    match comment_style(ext) {
-        CommentStyle::CStyle => {
-            trimmed.starts_with("//")
-                || trimmed.starts_with("/*")
-                || trimmed.starts_with('*')
-        }
-        CommentStyle::Hash => trimmed.starts_with('#'),
-        CommentStyle::DoubleDash => trimmed.starts_with("--"),
-        CommentStyle::Percent => trimmed.starts_with('%'),
-        CommentStyle::Semicolon => trimmed.starts_with(';'),
-        CommentStyle::Unknown => false,
-    }
-}
-
-pub fn analyze_file(path: &Path, content: &str, stats: &mut CodeStats) {
-    stats.total_files += 1;
+    pub items: ItemCounts,
+    /// Files whose `items`, declarations and `functions` came from a
+    /// tree-sitter parse rather than the line heuristics.
+    pub parsed_files: usize,
+    /// Every function found, with the file it is in.
+    pub functions: Vec<(PathBuf, FunctionMetrics)>,
+    /// `--todos` markers, in tree order.
+    pub todos: Vec<Todo>,
+    /// What `analyze_tree` found in each file, for the tree's annotations.
+    pub files: HashMap<PathBuf, FileAnalysis>,
+}
 
-    let ext = path
-        .extension()
-        .and_then(|s| s.to_str())
-        .unwrap_or("unknown")
-        .to_string();
+/// One file's share of `CodeStats`.
+#[derive(Debug, Default, Clone)]
+pub struct FileAnalysis {
+    pub counts: LanguageStats,
+    /// Highest cyclomatic complexity of its functions.
+    pub max_complexity: Option<usize>,
+}
 
-    *stats.files_by_extension.entry(ext.clone()).or_insert(0) += 1;
+/// Declarations found in a file.  Tests are also counted as functions or
+/// methods.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ItemCounts {
+    pub functions: usize,
+    pub methods: usize,
+    /// Structs, classes, enums, unions and type aliases.
+    pub types: usize,
+    /// Traits, interfaces and protocols.
+    pub traits: usize,
+    pub tests: usize,
+}
 
-    let mut line_count = 0usize;
+impl ItemCounts {
+    fn add(&mut self, other: &ItemCounts) {
+        self.functions += other.functions;
+        self.methods += other.methods;
+        self.types += other.types;
+        self.traits += other.traits;
+        self.tests += other.tests;
+    }
+}
 
-    for line in content.lines() {
-        line_count += 1;
-        let trimmed = line.trim();
+/// Line and file counts for one language.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LanguageStats {
+    pub files: usize,
+    pub lines: usize,
+    pub code: usize,
+    pub comments: usize,
+    pub docs: usize,
+    pub blanks: usize,
+}
 
-        if trimmed.is_empty() {
-            stats.blank_lines += 1;
-            continue;
+impl LanguageStats {
+    pub fn add(&mut self, other: &LanguageStats) {
+        self.files += other.files;
+        self.lines += other.lines;
+        self.code += other.code;
+        self.comments += other.comments;
+        self.docs += other.docs;
+        self.blanks += other.blanks;
+    }
+}
+
+/// `--annotate` text for a file or directory; comments include doc
+/// comments.  `None` when nothing below it was analyzed.
+pub fn annotation(counts: &LanguageStats, what: Annotate) -> Option<String> {
+    if counts.files == 0 {
+        return None;
+    }
+    let comments = counts.comments + counts.docs;
+    Some(match what {
+        Annotate::Counts => format!(
+            "{} code, {} comment, {} blank",
+            counts.code, comments, counts.blanks
+        ),
+        Annotate::Density => {
+            let non_blank = counts.code + comments;
+            if non_blank == 0 {
+                return None;
+            }
+            format!("{:.0}% comments", comments as f64 / non_blank as f64 * 100.0)
+        }
+    })
+}
+
+/// One row of the per-language table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguageRow {
+    pub language: String,
+    #[serde(flatten)]
+    pub stats: LanguageStats,
+    /// Share of all analyzed lines.
+    pub percent: f64,
+}
+
+/// The per-language table with its totals, as emitted in `--format json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LanguageTable {
+    pub languages: Vec<LanguageRow>,
+    pub total: LanguageStats,
+}
+
+impl LanguageTable {
+    pub fn new(stats: &CodeStats, sort: AnalyzeSort) -> Self {
+        let mut total = LanguageStats::default();
+        for s in stats.languages.values() {
+            total.add(s);
         }
+        let percent = |lines: usize| {
+            if total.lines == 0 {
+                0.0
+            } else {
+                (lines as f64 / total.lines as f64 * 1000.0).round() / 10.0
+            }
+        };
+        let mut languages: Vec<LanguageRow> = stats
+            .languages
+            .iter()
+            .map(|(name, s)| LanguageRow {
+                language: name.clone(),
+                stats: s.clone(),
+                percent: percent(s.lines),
+            })
+            .collect();
+        // Numeric columns largest first; ties and the name column A–Z.
+        languages.sort_by(|a, b| {
+            let key = |r: &LanguageRow| match sort {
+                AnalyzeSort::Language => 0,
+                AnalyzeSort::Files => r.stats.files,
+                AnalyzeSort::Lines | AnalyzeSort::Percent => r.stats.lines,
+                AnalyzeSort::Code => r.stats.code,
+                AnalyzeSort::Comments => r.stats.comments,
+                AnalyzeSort::Docs => r.stats.docs,
+                AnalyzeSort::Blanks => r.stats.blanks,
+            };
+            key(b).cmp(&key(a)).then_with(|| a.language.cmp(&b.language))
+        });
+        Self { languages, total }
+    }
 
-        if is_comment_line(trimmed, &ext) {
-            stats.comment_lines += 1;
-            continue;
+    /// Aligned text table with a total row.
+    pub fn format(&self) -> String {
+        let name_width = self
+            .languages
+            .iter()
+            .map(|r| r.language.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("Language".len());
+        let header = format!(
+            "{:<w$} {:>6} {:>9} {:>9} {:>9} {:>9} {:>9} {:>7}",
+            "Language", "Files", "Lines", "Code", "Comments", "Docs", "Blanks", "%",
+            w = name_width
+        );
+        let rule = "─".repeat(header.chars().count());
+        let row = |name: &str, s: &LanguageStats, pct: f64| {
+            format!(
+                "{:<w$} {:>6} {:>9} {:>9} {:>9} {:>9} {:>9} {:>6.1}%\n",
+                name, s.files, s.lines, s.code, s.comments, s.docs, s.blanks, pct,
+                w = name_width
+            )
+        };
+        let mut out = format!("{}\n{}\n", header, rule);
+        for r in &self.languages {
+            out.push_str(&row(&r.language, &r.stats, r.percent));
+        }
+        out.push_str(&rule);
+        out.push('\n');
+        let total_pct = if self.total.lines == 0 { 0.0 } else { 100.0 };
+        out.push_str(&row("Total", &self.total, total_pct));
+        out
+    }
+
+    /// CSV with a header row and no total; fields needing it are quoted.
+    pub fn to_csv(&self) -> String {
+        let mut out = String::from("language,files,lines,code,comments,docs,blanks,percent\n");
+        for r in &self.languages {
+            let name = if r.language.contains([',', '"']) {
+                format!("\"{}\"", r.language.replace('"', "\"\""))
+            } else {
+                r.language.clone()
+            };
+            let s = &r.stats;
+            out.push_str(&format!(
+                "{},{},{},{},{},{},{},{:.1}\n",
+                name, s.files, s.lines, s.code, s.comments, s.docs, s.blanks, r.percent
+            ));
+        }
+        out
+    }
+}
+
+/// Analyze every readable text file below `node`, skipping files above
+/// `max_size`.  Each file's results are also kept in `CodeStats::files`, for
+/// the tree view's annotations.
+pub fn analyze_tree(node: &Node, max_size: Option<u64>, stats: &mut CodeStats) {
+    for child in &node.children {
+        match child.kind {
+            NodeKind::Dir => analyze_tree(child, max_size, stats),
+            NodeKind::File if max_size.is_some_and(|max| child.size > max) => {}
+            NodeKind::File => {
+                if let Ok(Content::Text { text, .. }) = content::read(&child.path) {
+                    let before = stats.functions.len();
+                    let counts = analyze_file(&child.path, child.lang, &text, stats);
+                    let max_complexity = max_complexity(stats, before);
+                    stats.files.insert(child.path.clone(), FileAnalysis { counts, max_complexity });
+                }
+            }
+            NodeKind::Symlink => {}
+        }
+    }
+}
+
+fn tally(kinds: &[LineKind]) -> LanguageStats {
+    let mut counts = LanguageStats {
+        files: 1,
+        lines: kinds.len(),
+        ..LanguageStats::default()
+    };
+    for kind in kinds {
+        match kind {
+            LineKind::Blank => counts.blanks += 1,
+            LineKind::Comment => counts.comments += 1,
+            LineKind::Doc => counts.docs += 1,
+            LineKind::Code => counts.code += 1,
         }
+    }
+    counts
+}
 
-        // Code line
-        stats.code_lines += 1;
+/// Add one file of `language` to `stats`, and return its line counts.
+pub fn analyze_file(
+    path: &Path,
+    language: Option<&'static Language>,
+    content: &str,
+    stats: &mut CodeStats,
+) -> LanguageStats {
+    stats.total_files += 1;
+
+    let syntax = Syntax::for_language(language);
+    let scan = lexer::scan(content, &syntax);
+    let counts = tally(&scan.kinds);
+
+    let functions = match language.and_then(|l| parse(l.id, content)) {
+        Some((items, decls, functions)) => {
+            stats.items.add(&items);
+            stats.int_count += decls.int;
+            stats.float_count += decls.float;
+            stats.string_count += decls.string;
+            stats.bool_count += decls.bool;
+            stats.parsed_files += 1;
+            functions
+        }
+        None => {
+            count_by_lines(content, &scan.kinds, stats);
+            language.map(|l| complexity::measure(l.id, &scan.code)).unwrap_or_default()
+        }
+    };
+    stats.functions.extend(functions.into_iter().map(|f| (path.to_path_buf(), f)));
+
+    stats.total_lines += counts.lines;
+    stats.blank_lines += counts.blanks;
+    stats.comment_lines += counts.comments;
+    stats.doc_lines += counts.docs;
+    stats.code_lines += counts.code;
+    let name = language.map_or(OTHER, |l| l.name);
+    stats.languages.entry(name.to_string()).or_default().add(&counts);
+    counts
+}
+
+/// The line heuristics, for files tree-sitter can't parse: declarations and
+/// items counted from code lines.  They can't tell methods from functions.
+fn count_by_lines(content: &str, kinds: &[LineKind], stats: &mut CodeStats) {
+    let mut items = ItemCounts::default();
+
+    for (line, &kind) in content.lines().zip(kinds) {
+        let trimmed = line.trim();
+        if kind != LineKind::Code {
+            continue;
+        }
 
         // --- approximate type declarations ---
-        // Integer types (language-sensitive would be ideal; this is "approximate")
-        if trimmed.contains(": i32")
-            || trimmed.contains(": i64")
-            || trimmed.contains(": i128")
-            || trimmed.contains(": isize")
-            || trimmed.contains(": u32")
-            || trimmed.contains(": u64")
-            || trimmed.contains(": usize")
-            || trimmed.contains("int ")
-            || trimmed.contains("int\t")
-            || trimmed.starts_with("int ")
-        {
+        let ints = ["i32", "i64", "i128", "isize", "u32", "u64", "usize", "int"];
+        if declares(trimmed, &ints) {
             stats.int_count += 1;
         }
-        if trimmed.contains(": f32")
-            || trimmed.contains(": f64")
-            || trimmed.contains("float ")
-            || trimmed.contains("double ")
-        {
+        if declares(trimmed, &["f32", "f64", "float", "double"]) {
             stats.float_count += 1;
         }
-        if trimmed.contains(": String")
-            || trimmed.contains(": &str")
-            || trimmed.contains(": str")
-            || trimmed.contains("string ")
-            || trimmed.contains("String ")
-            || (trimmed.contains("str ") && !trimmed.contains("struct"))
-        {
+        if declares(trimmed, &["String", "str", "string"]) {
             stats.string_count += 1;
         }
-        if trimmed.contains(": bool")
-            || trimmed.contains("bool ")
-            || trimmed.contains("boolean ")
-            || trimmed.contains("Bool ")
-        {
+        if declares(trimmed, &["bool", "boolean", "Bool"]) {
             stats.bool_count += 1;
         }
 
-        // --- functions / classes ---
+        // --- functions / types ---
+        if trimmed.starts_with("#[test]")
+            || trimmed.starts_with("#[tokio::test")
+            || trimmed.starts_with("@Test")
+            || trimmed.starts_with("def test")
+            || trimmed.starts_with("func Test")
+        {
+            items.tests += 1;
+        }
         let is_fn = trimmed.starts_with("fn ")
             || trimmed.starts_with("pub fn ")
             || trimmed.starts_with("async fn ")
@@ -146,64 +350,467 @@ pub fn analyze_file(path: &Path, content: &str, stats: &mut CodeStats) {
             || (trimmed.starts_with("fun ")   // Kotlin
                 || trimmed.starts_with("pub fun "));
         if is_fn {
-            stats.function_count += 1;
+            items.functions += 1;
         }
 
-        let is_class = trimmed.starts_with("class ")
+        let is_type = trimmed.starts_with("class ")
             || trimmed.starts_with("struct ")
-            || trimmed.starts_with("impl ")
-            || trimmed.starts_with("pub impl ")
-            || trimmed.starts_with("trait ")
-            || trimmed.starts_with("pub trait ")
+            || trimmed.starts_with("pub struct ")
             || trimmed.starts_with("enum ")
             || trimmed.starts_with("pub enum ")
-            || trimmed.starts_with("interface ")
             || trimmed.starts_with("abstract class ")
             || trimmed.starts_with("object ");   // Kotlin / Scala singleton
-        if is_class {
-            stats.class_count += 1;
+        if is_type {
+            items.types += 1;
+        }
+        let is_trait = trimmed.starts_with("trait ")
+            || trimmed.starts_with("pub trait ")
+            || trimmed.starts_with("interface ");
+        if is_trait {
+            items.traits += 1;
+        }
+    }
+
+    stats.items.add(&items);
+}
+
+/// Whether `line` declares something of one of `types`, C-style (`int n`)
+/// or annotated (`n: i32`, `s: &str`).  The type must be a whole word, so
+/// `print x` or `u32x4` don't count.
+fn declares(line: &str, types: &[&str]) -> bool {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+    types.iter().any(|ty| {
+        line.match_indices(ty).any(|(i, _)| {
+            let (before, after) = (&line[..i], &line[i + ty.len()..]);
+            if before.ends_with(is_ident) || after.starts_with(is_ident) {
+                return false;
+            }
+            after.starts_with([' ', '\t']) || before.trim_end_matches('&').trim_end().ends_with(':')
+        })
+    })
+}
+
+#[cfg(feature = "tree-sitter")]
+fn parse(
+    lang_id: &str,
+    content: &str,
+) -> Option<(ItemCounts, Declarations, Vec<FunctionMetrics>)> {
+    crate::treesitter::parse(lang_id, content)
+}
+
+#[cfg(not(feature = "tree-sitter"))]
+fn parse(
+    _lang_id: &str,
+    _content: &str,
+) -> Option<(ItemCounts, Declarations, Vec<FunctionMetrics>)> {
+    None
+}
+
+/// The highest complexity among functions added to `stats` since it held
+/// `from` of them, i.e. in the file just analyzed.
+fn max_complexity(stats: &CodeStats, from: usize) -> Option<usize> {
+    stats.functions[from..].iter().map(|(_, f)| f.complexity).max()
+}
+
+/// Variable, field and parameter declarations by type: exact for files
+/// tree-sitter parsed, approximate for the rest.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Declarations {
+    pub int: usize,
+    pub float: usize,
+    pub string: usize,
+    pub bool: usize,
+}
+
+/// Everything `--analyze` reports, decoupled from the tree so any output
+/// format can carry it: embedded under `analysis` in `--format json`, or
+/// rendered on its own as text, JSON, CSV or Markdown (`--analyze-format`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalysisReport {
+    #[serde(flatten)]
+    pub table: LanguageTable,
+    pub items: ItemCounts,
+    pub declarations: Declarations,
+    /// Files whose items came from a tree-sitter parse.
+    pub parsed_files: usize,
+    /// Function metrics, present with `--analyze=complexity`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub complexity: Option<ComplexityReport>,
+}
+
+/// Function metrics over all analyzed files.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComplexityReport {
+    pub functions: usize,
+    /// One decimal, like `avg_length`.
+    pub avg_complexity: f64,
+    pub max_complexity: usize,
+    pub max_nesting: usize,
+    pub avg_length: f64,
+    pub max_length: usize,
+    /// The `--top` most complex functions, most complex first.
+    pub top: Vec<RankedFunction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedFunction {
+    /// Relative to the scanned directory, `/`-separated.
+    pub path: String,
+    #[serde(flatten)]
+    pub metrics: FunctionMetrics,
+}
+
+impl AnalysisReport {
+    /// With `complexity` set to `Some(n)` the report carries function
+    /// metrics and the `n` most complex functions, located relative to
+    /// `base`.
+    pub fn new(stats: &CodeStats, sort: AnalyzeSort, complexity: Option<usize>, base: &Path) -> Self {
+        Self {
+            table: LanguageTable::new(stats, sort),
+            items: stats.items.clone(),
+            declarations: Declarations {
+                int: stats.int_count,
+                float: stats.float_count,
+                string: stats.string_count,
+                bool: stats.bool_count,
+            },
+            parsed_files: stats.parsed_files,
+            complexity: complexity.map(|top| ComplexityReport::new(&stats.functions, top, base)),
+        }
+    }
+
+    pub fn render(&self, format: AnalyzeFormat) -> Result<String, String> {
+        Ok(match format {
+            AnalyzeFormat::Text => self.to_text(),
+            AnalyzeFormat::Json => {
+                serde_json::to_string_pretty(self).map_err(|e| e.to_string())? + "\n"
+            }
+            AnalyzeFormat::Csv => self.table.to_csv(),
+            AnalyzeFormat::Markdown => self.to_markdown(),
+        })
+    }
+
+    fn density(&self) -> Option<f64> {
+        let total = &self.table.total;
+        (total.lines > 0).then(|| total.code as f64 / total.lines as f64 * 100.0)
+    }
+
+    fn source(&self) -> String {
+        if self.parsed_files == 0 {
+            "approximate".to_string()
+        } else {
+            format!("tree-sitter for {} of {} files", self.parsed_files, self.table.total.files)
+        }
+    }
+
+    /// Label and value of each code-element row.
+    fn elements(&self) -> [(&'static str, usize); 9] {
+        let (items, decls) = (&self.items, &self.declarations);
+        [
+            ("Functions", items.functions),
+            ("Methods", items.methods),
+            ("Types", items.types),
+            ("Traits", items.traits),
+            ("Tests", items.tests),
+            ("Int declarations", decls.int),
+            ("Float decls", decls.float),
+            ("String decls", decls.string),
+            ("Bool decls", decls.bool),
+        ]
+    }
+
+    /// The report printed after the tree.
+    pub fn to_text(&self) -> String {
+        let total = &self.table.total;
+        let mut out = String::new();
+
+        out.push_str(&format!("Total Files:   {}\n", total.files));
+        out.push_str(&format!("Total Lines:   {}\n", total.lines));
+        out.push_str(&format!("Blank Lines:   {}\n", total.blanks));
+        out.push_str(&format!("Comment Lines: {}\n", total.comments));
+        out.push_str(&format!("Doc Lines:     {}\n", total.docs));
+        out.push_str(&format!("Code Lines:    {}\n", total.code));
+        if let Some(pct) = self.density() {
+            out.push_str(&format!("Code Density:  {:.1}%\n", pct));
+        }
+
+        out.push('\n');
+        out.push_str(&self.table.format());
+
+        out.push_str(&format!("\nCode Elements ({}):\n", self.source()));
+        for (label, n) in self.elements() {
+            out.push_str(&format!("  {:<18}{}\n", format!("{}:", label), n));
+        }
+
+        if let Some(c) = &self.complexity {
+            out.push_str(&c.to_text());
+        }
+        out
+    }
+
+    /// GitHub-flavored Markdown tables, headed `## Code analysis`.
+    pub fn to_markdown(&self) -> String {
+        let total = &self.table.total;
+        let mut out = String::from("## Code analysis\n\n| Metric | Value |\n|---|---:|\n");
+        for (label, n) in [
+            ("Files", total.files),
+            ("Lines", total.lines),
+            ("Code", total.code),
+            ("Comments", total.comments),
+            ("Docs", total.docs),
+            ("Blanks", total.blanks),
+        ] {
+            out.push_str(&format!("| {} | {} |\n", label, n));
+        }
+        if let Some(pct) = self.density() {
+            out.push_str(&format!("| Code density | {:.1}% |\n", pct));
         }
+
+        out.push_str("\n### Languages\n\n");
+        out.push_str("| Language | Files | Lines | Code | Comments | Docs | Blanks | % |\n");
+        out.push_str("|---|---:|---:|---:|---:|---:|---:|---:|\n");
+        let row = |name: &str, s: &LanguageStats, pct: f64| {
+            format!(
+                "| {} | {} | {} | {} | {} | {} | {} | {:.1} |\n",
+                name, s.files, s.lines, s.code, s.comments, s.docs, s.blanks, pct
+            )
+        };
+        for r in &self.table.languages {
+            out.push_str(&row(&output::markdown_escape(&r.language), &r.stats, r.percent));
+        }
+        let total_pct = if total.lines == 0 { 0.0 } else { 100.0 };
+        out.push_str(&row("**Total**", total, total_pct));
+
+        out.push_str(&format!("\n### Code elements ({})\n\n", self.source()));
+        out.push_str("| Element | Count |\n|---|---:|\n");
+        for (label, n) in self.elements() {
+            out.push_str(&format!("| {} | {} |\n", label, n));
+        }
+
+        if let Some(c) = &self.complexity {
+            out.push_str(&c.to_markdown());
+        }
+        out
+    }
+}
+
+impl ComplexityReport {
+    fn new(functions: &[(PathBuf, FunctionMetrics)], top: usize, base: &Path) -> Self {
+        let n = functions.len().max(1) as f64;
+        let sum = |f: fn(&FunctionMetrics) -> usize| functions.iter().map(|(_, m)| f(m)).sum::<usize>();
+        let max = |f: fn(&FunctionMetrics) -> usize| functions.iter().map(|(_, m)| f(m)).max().unwrap_or(0);
+        let avg = |total: usize| (total as f64 / n * 10.0).round() / 10.0;
+
+        // Most complex first; ties by length, then by location.
+        let mut ranked: Vec<&(PathBuf, FunctionMetrics)> = functions.iter().collect();
+        ranked.sort_by(|(pa, a), (pb, b)| {
+            b.complexity
+                .cmp(&a.complexity)
+                .then(b.length.cmp(&a.length))
+                .then_with(|| pa.cmp(pb))
+                .then(a.line.cmp(&b.line))
+        });
+        ranked.truncate(top);
+
+        Self {
+            functions: functions.len(),
+            avg_complexity: avg(sum(|m| m.complexity)),
+            max_complexity: max(|m| m.complexity),
+            max_nesting: max(|m| m.nesting),
+            avg_length: avg(sum(|m| m.length)),
+            max_length: max(|m| m.length),
+            top: ranked
+                .into_iter()
+                .map(|(path, metrics)| RankedFunction {
+                    path: output::relative_path(path, base),
+                    metrics: metrics.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    fn to_text(&self) -> String {
+        let mut out = String::from("\nFunction Metrics:\n");
+        out.push_str(&format!("  Functions:        {}\n", self.functions));
+        if self.functions == 0 {
+            return out;
+        }
+        out.push_str(&format!("  Avg complexity:   {:.1}\n", self.avg_complexity));
+        out.push_str(&format!("  Max complexity:   {}\n", self.max_complexity));
+        out.push_str(&format!("  Max nesting:      {}\n", self.max_nesting));
+        out.push_str(&format!("  Avg length:       {:.1} lines\n", self.avg_length));
+        out.push_str(&format!("  Max length:       {} lines\n", self.max_length));
+
+        if self.top.is_empty() {
+            return out;
+        }
+        let name_width = self
+            .top
+            .iter()
+            .map(|f| f.metrics.name.chars().count())
+            .max()
+            .unwrap_or(0)
+            .max("Function".len());
+        out.push_str(&format!(
+            "\nMost Complex Functions (top {} of {}):\n",
+            self.top.len(),
+            self.functions
+        ));
+        out.push_str(&format!(
+            "  {:>4} {:>5} {:>6}  {:<w$}  {}\n",
+            "CC", "Depth", "Lines", "Function", "Location",
+            w = name_width
+        ));
+        for RankedFunction { path, metrics: f } in &self.top {
+            out.push_str(&format!(
+                "  {:>4} {:>5} {:>6}  {:<w$}  {}:{}\n",
+                f.complexity,
+                f.nesting,
+                f.length,
+                f.name,
+                path,
+                f.line,
+                w = name_width
+            ));
+        }
+        out
     }
 
-    stats.total_lines += line_count;
-    *stats.lines_by_extension.entry(ext).or_insert(0) += line_count;
+    fn to_markdown(&self) -> String {
+        let mut out = String::from("\n### Function metrics\n\n| Metric | Value |\n|---|---:|\n");
+        out.push_str(&format!("| Functions | {} |\n", self.functions));
+        if self.functions == 0 {
+            return out;
+        }
+        out.push_str(&format!("| Avg complexity | {:.1} |\n", self.avg_complexity));
+        out.push_str(&format!("| Max complexity | {} |\n", self.max_complexity));
+        out.push_str(&format!("| Max nesting | {} |\n", self.max_nesting));
+        out.push_str(&format!("| Avg length | {:.1} |\n", self.avg_length));
+        out.push_str(&format!("| Max length | {} |\n", self.max_length));
+
+        if self.top.is_empty() {
+            return out;
+        }
+        out.push_str(&format!(
+            "\n### Most complex functions (top {} of {})\n\n",
+            self.top.len(),
+            self.functions
+        ));
+        out.push_str("| CC | Depth | Lines | Function | Location |\n|---:|---:|---:|---|---|\n");
+        for RankedFunction { path, metrics: f } in &self.top {
+            out.push_str(&format!(
+                "| {} | {} | {} | `{}` | {}:{} |\n",
+                f.complexity,
+                f.nesting,
+                f.length,
+                f.name,
+                output::markdown_escape(path),
+                f.line
+            ));
+        }
+        out
+    }
 }
 
-pub fn format_analysis(stats: &CodeStats) -> String {
-    let mut out = String::new();
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
 
-    out.push_str(&format!("Total Files:   {}\n", stats.total_files));
-    out.push_str(&format!("Total Lines:   {}\n", stats.total_lines));
-    out.push_str(&format!("Blank Lines:   {}\n", stats.blank_lines));
-    out.push_str(&format!("Comment Lines: {}\n", stats.comment_lines));
-    out.push_str(&format!("Code Lines:    {}\n", stats.code_lines));
+    use super::{AnalysisReport, CodeStats, LanguageTable, analyze_file, analyze_tree, declares};
+    use crate::cli::{AnalyzeFormat, AnalyzeSort};
+    use crate::lang;
+    use crate::walk::{WalkOptions, build_tree};
 
-    if stats.total_lines > 0 {
-        let pct = stats.code_lines as f64 / stats.total_lines as f64 * 100.0;
-        out.push_str(&format!("Code Density:  {:.1}%\n", pct));
+    fn add(path: &str, content: &str, stats: &mut CodeStats) {
+        let path = Path::new(path);
+        analyze_file(path, lang::detect(path, Some(content)), content, stats);
     }
 
-    out.push_str("\nFiles by Extension:\n");
-    let mut ext_vec: Vec<_> = stats.files_by_extension.iter().collect();
-    ext_vec.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
-    for (ext, count) in &ext_vec {
-        out.push_str(&format!("  .{:<10} {} files\n", ext, count));
+    #[test]
+    fn test_language_table() {
+        let mut stats = CodeStats::default();
+        add("src/a.h", "#include <vector>\nclass A;\n", &mut stats);
+        add("src/a.cpp", "// impl\n\nint f() { return 1; }\n", &mut stats);
+        add("x.py", "\"\"\"Doc.\"\"\"\nx = 1\n", &mut stats);
+
+        let table = LanguageTable::new(&stats, AnalyzeSort::Code);
+        let names: Vec<&str> = table.languages.iter().map(|r| r.language.as_str()).collect();
+        assert_eq!(names, ["C++", "Python"]);
+        let cpp = &table.languages[0].stats;
+        assert_eq!((cpp.files, cpp.lines, cpp.code, cpp.comments, cpp.blanks), (2, 5, 3, 1, 1));
+        assert_eq!(table.total.docs, 1);
+        assert_eq!(table.languages[0].percent, 71.4);
+
+        let by_name = LanguageTable::new(&stats, AnalyzeSort::Language);
+        assert_eq!(by_name.languages[0].language, "C++");
+        assert_eq!(
+            by_name.to_csv(),
+            "language,files,lines,code,comments,docs,blanks,percent\n\
+             C++,2,5,3,1,0,1,71.4\n\
+             Python,1,2,1,0,1,0,28.6\n"
+        );
     }
 
-    out.push_str("\nLines by Extension:\n");
-    let mut lines_vec: Vec<_> = stats.lines_by_extension.iter().collect();
-    lines_vec.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
-    for (ext, count) in &lines_vec {
-        out.push_str(&format!("  .{:<10} {} lines\n", ext, count));
+    #[test]
+    fn test_report_formats() {
+        let mut stats = CodeStats::default();
+        let src = "fn simple() {}\n\nfn branchy(x: i32) {\n    if x > 0 && x < 9 {\n        run();\n    }\n}\n";
+        add("/p/src/lib.rs", src, &mut stats);
+
+        let report = AnalysisReport::new(&stats, AnalyzeSort::Code, Some(1), Path::new("/p"));
+        let complexity = report.complexity.as_ref().unwrap();
+        assert_eq!((complexity.functions, complexity.max_complexity, complexity.avg_complexity), (2, 3, 2.0));
+        assert_eq!(complexity.top.len(), 1);
+        assert_eq!((complexity.top[0].path.as_str(), complexity.top[0].metrics.line), ("src/lib.rs", 3));
+
+        let json: serde_json::Value =
+            serde_json::from_str(&report.render(AnalyzeFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["total"]["code"], 6);
+        assert_eq!(json["items"]["functions"], 2);
+        assert_eq!(json["declarations"]["int"], 1);
+        assert_eq!(json["complexity"]["top"][0]["name"], "branchy");
+
+        let md = report.render(AnalyzeFormat::Markdown).unwrap();
+        assert!(md.starts_with("## Code analysis\n"));
+        assert!(md.contains("| Rust | 1 | 7 | 6 | 0 | 0 | 1 | 100.0 |\n"));
+        assert!(md.contains("| 3 | 1 | 5 | `branchy` | src/lib.rs:3 |\n"));
+
+        let text = report.render(AnalyzeFormat::Text).unwrap();
+        assert!(text.contains("  Functions:        2\n"));
+        assert!(text.contains("   3     1      5  branchy   src/lib.rs:3\n"));
+
+        let summary = AnalysisReport::new(&stats, AnalyzeSort::Code, None, Path::new("/p"));
+        assert!(!summary.render(AnalyzeFormat::Json).unwrap().contains("complexity"));
+        assert_eq!(summary.render(AnalyzeFormat::Csv).unwrap(), summary.table.to_csv());
     }
 
-    out.push_str("\nCode Elements (approximate):\n");
-    out.push_str(&format!("  Functions:        {}\n", stats.function_count));
-    out.push_str(&format!("  Classes/Structs:  {}\n", stats.class_count));
-    out.push_str(&format!("  Int declarations: {}\n", stats.int_count));
-    out.push_str(&format!("  Float decls:      {}\n", stats.float_count));
-    out.push_str(&format!("  String decls:     {}\n", stats.string_count));
-    out.push_str(&format!("  Bool decls:       {}\n", stats.bool_count));
+    #[test]
+    fn test_analyze_tree_keeps_file_results() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir(dir.path().join("src")).unwrap();
+        let lib = dir.path().join("src/lib.rs");
+        std::fs::write(&lib, "// lib\nfn f(x: bool) {\n    if x {}\n}\n").unwrap();
+        std::fs::write(dir.path().join("big.py"), "x = 1\n".repeat(10)).unwrap();
 
-    out
+        let tree = build_tree(dir.path(), &WalkOptions::default());
+        let mut stats = CodeStats::default();
+        analyze_tree(&tree, Some(50), &mut stats);
+
+        assert_eq!(stats.total_files, 1, "big.py is over the size limit");
+        let file = &stats.files[&lib];
+        assert_eq!((file.counts.lines, file.counts.code, file.counts.comments), (4, 3, 1));
+        assert_eq!(file.max_complexity, Some(2));
+    }
+
+    #[test]
+    fn test_declares() {
+        let ints = ["i32", "usize", "int"];
+        assert!(declares("int n = 0;", &ints));
+        assert!(declares("fn f(n: usize) {", &ints));
+        assert!(declares("let s: &str = x;", &["str"]));
+        assert!(!declares("print x", &ints));
+        assert!(!declares("let v = u32x4::splat(0);", &["u32"]));
+        assert!(!declares("pub struct Point {", &["str"]));
+        assert!(!declares("n = int(s)", &ints));
+    }
 }
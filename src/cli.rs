@@ -1,4 +1,4 @@
-use clap::{Parser, ValueEnum};
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
 pub enum ColorWhen {
@@ -20,6 +20,8 @@ pub enum SortKey {
     Size,
     Time,
     Ext,
+    /// Line count (directories by their total)
+    Lines,
     None,
 }
 
@@ -29,6 +31,125 @@ pub enum OutputFormat {
     Json,
     Markdown,
     List,
+    Html,
+    Dot,
+    Mermaid,
+    /// Tree plus file contents in tagged blocks, for code assistants
+    Pack,
+}
+
+/// What `--analyze` reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AnalyzeMode {
+    /// Line counts per language and code elements
+    Summary,
+    /// The summary plus per-function complexity, nesting and length
+    Complexity,
+}
+
+/// How the `--analyze` report is written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AnalyzeFormat {
+    /// Aligned tables, as printed after the tree
+    Text,
+    /// The report object also embedded in --format json
+    Json,
+    /// The per-language table
+    Csv,
+    /// Markdown tables
+    Markdown,
+}
+
+/// Per-file (and per-directory) annotation added to the tree by `--annotate`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Annotate {
+    /// Code, comment and blank line counts
+    Counts,
+    /// Comment lines as a share of non-blank lines
+    Density,
+}
+
+/// Column to order the `--analyze` language table by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum AnalyzeSort {
+    Language,
+    Files,
+    Lines,
+    Code,
+    Comments,
+    Docs,
+    Blanks,
+    /// Share of lines; same order as `lines`
+    Percent,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum HashAlgo {
+    Blake3,
+    Sha256,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum MarkdownStyle {
+    /// Nested bullet list with bold directories
+    Outline,
+    /// Fenced text block identical to the terminal tree
+    Tree,
+    /// Bullet list with relative links to every entry
+    Links,
+    /// Table of paths with size/lines/git columns
+    Table,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Compare two directories or JSON snapshots and show what changed
+    Diff(DiffArgs),
+    /// Track file counts, size and lines per language across git history
+    Stats(StatsArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DiffArgs {
+    /// Old side: a directory or a `--format json` snapshot
+    pub old: String,
+
+    /// New side: a directory or a `--format json` snapshot
+    pub new: String,
+
+    /// Also treat differing modification times as a change
+    #[arg(long)]
+    pub mtime: bool,
+
+    /// Compare file contents when sizes match (live directories only)
+    #[arg(long)]
+    pub content: bool,
+
+    /// Hide unchanged entries
+    #[arg(long)]
+    pub changes_only: bool,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct StatsArgs {
+    /// Directory inside a git repository (default: current directory)
+    pub directory: Option<String>,
+
+    /// Measure the last N commits of the current branch
+    #[arg(long, value_name = "N", default_value_t = 10, conflicts_with = "at")]
+    pub history: usize,
+
+    /// Measure these tags, branches or commits instead, in the order given
+    #[arg(long, value_name = "REV", value_delimiter = ',')]
+    pub at: Vec<String>,
+
+    /// Show each metric as a sparkline instead of a table
+    #[arg(long)]
+    pub sparkline: bool,
+
+    /// Don't analyze files larger than SIZE (e.g. 10M)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_file_size: Option<u64>,
 }
 
 #[derive(Parser, Debug)]
@@ -39,24 +160,27 @@ pub enum OutputFormat {
     long_about = None,
 )]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Directory to display (default: current directory)
     pub directory: Option<String>,
 
     // ── Display ───────────────────────────────────────────────────────────────
     /// When to use ANSI colors
-    #[arg(long, value_enum, default_value = "auto", value_name = "WHEN")]
+    #[arg(long, value_enum, default_value = "auto", value_name = "WHEN", global = true)]
     pub color: ColorWhen,
 
     /// When to show file-type icons (requires a Nerd Font)
-    #[arg(long, value_enum, default_value = "auto", value_name = "WHEN")]
+    #[arg(long, value_enum, default_value = "auto", value_name = "WHEN", global = true)]
     pub icons: IconWhen,
 
     /// Disable icons (shorthand for --icons=never)
-    #[arg(long, overrides_with = "icons")]
+    #[arg(long, overrides_with = "icons", global = true)]
     pub no_icons: bool,
 
     /// Use ASCII connectors instead of Unicode box-drawing characters
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub ascii: bool,
 
     /// Show the full path for each entry
@@ -65,60 +189,81 @@ pub struct Args {
 
     // ── Depth ─────────────────────────────────────────────────────────────────
     /// Limit display depth (also -L)
-    #[arg(short = 'd', long = "depth", visible_short_alias = 'L', value_name = "N")]
+    #[arg(short = 'd', long = "depth", visible_short_alias = 'L', value_name = "N", global = true)]
     pub depth: Option<usize>,
 
     // ── Filtering ─────────────────────────────────────────────────────────────
     /// Show hidden files and directories (starting with '.')
-    #[arg(short = 'a', long = "all")]
+    #[arg(short = 'a', long = "all", global = true)]
     pub show_hidden: bool,
 
     /// Do not respect .gitignore rules (they are respected by default)
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub no_gitignore: bool,
 
     /// Ignore a specific folder by name (repeatable; default list always applied)
-    #[arg(short = 'i', long = "ignore", value_name = "NAME", action = clap::ArgAction::Append)]
+    #[arg(short = 'i', long = "ignore", value_name = "NAME", action = clap::ArgAction::Append, global = true)]
     pub ignore_names: Vec<String>,
 
     /// Include only files matching this glob pattern (repeatable)
-    #[arg(short = 'P', long = "pattern", value_name = "GLOB", action = clap::ArgAction::Append)]
+    #[arg(short = 'P', long = "pattern", value_name = "GLOB", action = clap::ArgAction::Append, global = true)]
     pub include_globs: Vec<String>,
 
     /// Exclude files/directories matching this glob pattern (repeatable)
-    #[arg(short = 'I', long = "ignore-glob", value_name = "GLOB", action = clap::ArgAction::Append)]
+    #[arg(short = 'I', long = "ignore-glob", value_name = "GLOB", action = clap::ArgAction::Append, global = true)]
     pub exclude_globs: Vec<String>,
 
-    /// Show only files with this extension
-    #[arg(short = 'e', long = "extension", value_name = "EXT")]
+    /// Show only files with this extension or language (`rs`, `py`, `cpp`, `rust`); shebang
+    /// scripts and ambiguous headers are matched by their detected language
+    #[arg(short = 'e', long = "extension", value_name = "EXT", global = true)]
     pub extension: Option<String>,
 
     /// Show only directories (also: --only-folders for back-compat)
-    #[arg(short = 'D', long = "dirs-only", alias = "only-folders")]
+    #[arg(short = 'D', long = "dirs-only", alias = "only-folders", global = true)]
     pub dirs_only: bool,
 
     /// Show only files (no directories)
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub files_only: bool,
 
     /// Remove empty directories from the output
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub prune: bool,
 
+    /// Hide binary files (detected by sniffing their first bytes)
+    #[arg(long, global = true)]
+    pub text_only: bool,
+
+    /// Show only entries whose name fuzzy-matches QUERY, plus their parents
+    #[arg(long, value_name = "QUERY", global = true)]
+    pub find: Option<String>,
+
+    /// Treat the --find query as a regular expression
+    #[arg(long, requires = "find", global = true)]
+    pub regex: bool,
+
+    /// Show only files whose contents match the regex PATTERN, with match counts
+    #[arg(long, value_name = "PATTERN", conflicts_with = "watch")]
+    pub grep: Option<String>,
+
+    /// With --grep, print matching lines with N lines of context under each file
+    #[arg(long, value_name = "N", requires = "grep")]
+    pub grep_context: Option<usize>,
+
     // ── Sorting ───────────────────────────────────────────────────────────────
     /// Sort entries by the given key
-    #[arg(short = 's', long = "sort", value_enum, value_name = "KEY")]
+    #[arg(short = 's', long = "sort", value_enum, value_name = "KEY", global = true)]
     pub sort: Option<SortKey>,
 
     /// Reverse the sort order
-    #[arg(short = 'r', long)]
+    #[arg(short = 'r', long, global = true)]
     pub reverse: bool,
 
     /// List directories before files (default: true)
-    #[arg(long, default_value_t = true, overrides_with = "no_dirs_first")]
+    #[arg(long, default_value_t = true, overrides_with = "no_dirs_first", global = true)]
     pub dirs_first: bool,
 
-    #[arg(long, hide = true)]
+    #[arg(long, hide = true, global = true)]
     pub no_dirs_first: bool,
 
     // ── Git ───────────────────────────────────────────────────────────────────
@@ -132,11 +277,23 @@ pub struct Args {
     pub show_lines: bool,
 
     /// Show file sizes next to each file
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub sizes: bool,
 
-    /// Print a summary line at the end (file count, dir count, total size)
+    /// Don't read files larger than SIZE for line counts, code and analysis (e.g. 10M, 1.5G)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size, global = true)]
+    pub max_file_size: Option<u64>,
+
+    /// Compute a content digest for each file
+    #[arg(long, value_enum, value_name = "ALGO", global = true)]
+    pub hash: Option<HashAlgo>,
+
+    /// List groups of files with identical content after the tree
     #[arg(long)]
+    pub duplicates: bool,
+
+    /// Print a summary line at the end (file count, dir count, total size)
+    #[arg(long, global = true)]
     pub summary: bool,
 
     // ── Code analysis ─────────────────────────────────────────────────────────
@@ -148,9 +305,46 @@ pub struct Args {
     #[arg(long = "sc", hide = true)]
     pub sc_compat: bool,
 
-    /// Analyze code and show statistics
-    #[arg(long = "analyze", short = 'A')]
-    pub analyze: bool,
+    /// Count TODO/FIXME/HACK/XXX markers in comments per file and list them after the tree
+    #[arg(long)]
+    pub todos: bool,
+
+    /// Print file contents as-is, without masking secrets (--show-code, --format pack)
+    #[arg(long)]
+    pub no_redact: bool,
+
+    /// Analyze code and show statistics; `--analyze=complexity` adds function metrics
+    #[arg(
+        long = "analyze",
+        short = 'A',
+        value_enum,
+        value_name = "MODE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "summary"
+    )]
+    pub analyze: Option<AnalyzeMode>,
+
+    /// Order the --analyze language table by this column (numbers largest first)
+    #[arg(long, value_enum, value_name = "COLUMN", default_value = "code", requires = "analyze")]
+    pub analyze_sort: AnalyzeSort,
+
+    /// Annotate files and directories in the tree with their line counts or comment density
+    #[arg(long, value_enum, value_name = "WHAT", requires = "analyze")]
+    pub annotate: Option<Annotate>,
+
+    /// Number of functions listed by --analyze=complexity
+    #[arg(long, value_name = "N", default_value_t = 10, requires = "analyze")]
+    pub top: usize,
+
+    /// Write the --analyze report as text, JSON, CSV or Markdown (default: follows --format)
+    #[arg(long, value_enum, value_name = "FMT", requires = "analyze")]
+    pub analyze_format: Option<AnalyzeFormat>,
+
+    /// Save the --analyze report to a file, separate from the main output
+    /// (otherwise html, dot, mermaid, and json with a non-JSON report, print it to stderr)
+    #[arg(long, value_name = "FILE", requires = "analyze")]
+    pub analyze_output: Option<String>,
 
     /// Hidden alias kept for back-compat (-a in old version)
     // NOTE: -a is now taken by --all; old -a users should use -A
@@ -158,27 +352,107 @@ pub struct Args {
 
     // ── Output ────────────────────────────────────────────────────────────────
     /// Save output to a file instead of stdout
-    #[arg(short = 'o', long = "output", value_name = "FILE")]
+    #[arg(short = 'o', long = "output", value_name = "FILE", global = true)]
     pub output_file: Option<String>,
 
     /// Output format
-    #[arg(long = "format", value_enum, default_value = "tree", value_name = "FMT")]
+    #[arg(long = "format", value_enum, default_value = "tree", value_name = "FMT", global = true)]
     pub format: OutputFormat,
 
+    /// Markdown layout used by --format markdown
+    #[arg(long, value_enum, default_value = "outline", value_name = "STYLE")]
+    pub md_style: MarkdownStyle,
+
+    /// Content budget for --format pack, in estimated tokens
+    #[arg(long, value_name = "N")]
+    pub max_tokens: Option<u64>,
+
+    /// Content budget for --format pack, in bytes (e.g. 500K)
+    #[arg(long, value_name = "SIZE", value_parser = parse_size)]
+    pub max_bytes: Option<u64>,
+
+    /// Draw directories with at least N entries as a cluster box (dot/mermaid)
+    #[arg(long, value_name = "N")]
+    pub cluster: Option<usize>,
+
+    /// Keep running and redraw the tree whenever the directory changes
+    #[arg(long, conflicts_with_all = ["from_json", "output_file"])]
+    pub watch: bool,
+
+    /// Browse the tree in an interactive terminal UI
+    #[arg(short = 't', long, conflicts_with_all = ["from_json", "watch", "output_file"])]
+    pub tui: bool,
+
+    /// With --tui, print the picked path to stdout on exit (for shell scripts)
+    #[arg(long, requires = "tui")]
+    pub print_selected: bool,
+
+    /// Render a tree saved with --format json instead of scanning a directory
+    #[arg(long, value_name = "FILE", conflicts_with = "directory")]
+    pub from_json: Option<String>,
+
+    /// Print the JSON Schema describing --format json output and exit
+    #[arg(long)]
+    pub json_schema: bool,
+
     // ── Config / themes ───────────────────────────────────────────────────────
     /// Use a specific config file
-    #[arg(long, value_name = "FILE")]
+    #[arg(long, value_name = "FILE", global = true)]
     pub config: Option<String>,
 
     /// Select a named theme from the config file
-    #[arg(long, value_name = "NAME")]
+    #[arg(long, value_name = "NAME", global = true)]
     pub theme: Option<String>,
 
     /// Ignore the config file entirely
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub no_config: bool,
 
     /// Write a default config file to the config directory and exit
     #[arg(long)]
     pub generate_config: bool,
 }
+
+/// Parse a size such as `512`, `64K`, `10M`, `1.5G` (binary units, as shown by
+/// `--sizes`; a trailing `B`/`iB` is accepted).
+pub fn parse_size(s: &str) -> Result<u64, String> {
+    let t = s.trim();
+    let t = t.strip_suffix("iB").or_else(|| t.strip_suffix('B')).unwrap_or(t);
+    let (num, mult) = match t.char_indices().last() {
+        Some((i, c)) if c.is_ascii_alphabetic() => {
+            let mult: u64 = match c.to_ascii_uppercase() {
+                'K' => 1 << 10,
+                'M' => 1 << 20,
+                'G' => 1 << 30,
+                'T' => 1 << 40,
+                _ => return Err(format!("unknown size unit in '{}'", s)),
+            };
+            (&t[..i], mult)
+        }
+        _ => (t, 1),
+    };
+    let value: f64 = num
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid size '{}'", s))?;
+    if !value.is_finite() || value < 0.0 {
+        return Err(format!("invalid size '{}'", s));
+    }
+    Ok((value * mult as f64) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_size;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("64k"), Ok(64 * 1024));
+        assert_eq!(parse_size("10MB"), Ok(10 << 20));
+        assert_eq!(parse_size("1.5G"), Ok(3 << 29));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert!(parse_size("12X").is_err());
+        assert!(parse_size("-1").is_err());
+    }
+}
@@ -0,0 +1,479 @@
+//! Per-function cyclomatic complexity, nesting depth and length for
+//! `--analyze=complexity`, from the source as `lexer::scan` masks it (so
+//! keywords and braces in comments and strings don't count).
+//!
+//! Functions are found by keyword (`fn`, `def`, `func`, `function`, …) or,
+//! in C-family languages, by a `type name(…)` head, and their bodies by
+//! braces, or by indentation in Python.  Closures and lambdas count toward
+//! the function they are written in.  Builds with `--features tree-sitter`
+//! get exact numbers for the languages it parses.
+
+use serde::{Deserialize, Serialize};
+
+/// One function's metrics.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FunctionMetrics {
+    pub name: String,
+    /// Line of the function head, from 1.
+    pub line: usize,
+    /// 1 + the number of decision points: branches, loops, case arms,
+    /// `catch`/`except` clauses, ternaries and `&&`/`||`.
+    pub complexity: usize,
+    /// Deepest nesting of control blocks (`if`, loops, `match`/`switch`,
+    /// `try`) in the body.
+    pub nesting: usize,
+    /// Lines from the head to the end of the body.
+    pub length: usize,
+}
+
+/// Words that add a decision point.
+const DECISIONS: &[&str] = &[
+    "if", "elif", "elsif", "for", "foreach", "while", "until", "unless", "case", "catch",
+    "except", "when", "guard",
+];
+
+/// Words that make the block they open a control block.
+const CONTROL: &[&str] = &[
+    "if", "else", "elif", "for", "foreach", "while", "until", "unless", "loop", "do", "match",
+    "switch", "select", "try", "catch", "except", "finally", "with", "when", "guard",
+];
+
+/// Words that introduce a named function.
+const FN_KEYWORDS: &[&str] = &["fn", "func", "function", "fun", "def", "sub", "proc"];
+
+/// Languages whose functions are declared as `type name(params) {`.
+const C_FAMILY: &[&str] = &[
+    "c", "cpp", "objectivec", "java", "csharp", "dart", "groovy", "d", "vala",
+];
+
+/// Languages where `and`/`or` are boolean operators.
+const WORD_BOOLEANS: &[&str] = &["python", "ruby", "lua", "perl", "nim", "elixir", "crystal"];
+
+/// Metrics for every function found in `masked` source of language `lang_id`.
+pub fn measure(lang_id: &str, masked: &str) -> Vec<FunctionMetrics> {
+    if lang_id == "python" {
+        measure_indented(lang_id, masked)
+    } else {
+        measure_braced(lang_id, masked)
+    }
+}
+
+/// A function head waiting for its `{`.
+struct Pending {
+    name: String,
+    line: usize,
+    /// Byte offset on the head line where the signature starts.
+    from: usize,
+    /// `(`/`[` depth, so `;` and `=` in parameter lists don't end the head.
+    depth: usize,
+}
+
+enum Brace {
+    /// Body of the function at this index in the result.
+    Body(usize),
+    Control,
+    Other,
+}
+
+fn measure_braced(lang_id: &str, masked: &str) -> Vec<FunctionMetrics> {
+    let mut functions: Vec<FunctionMetrics> = Vec::new();
+    let mut braces: Vec<Brace> = Vec::new();
+    let mut pending: Option<Pending> = None;
+    // A control word was seen in the statement so far.
+    let mut control = false;
+    // `(` depth, so the `;`s of a C `for (;;)` don't end its statement.
+    let mut parens = 0usize;
+
+    for (idx, text) in masked.lines().enumerate() {
+        let line = idx + 1;
+        if let Some(p) = &pending
+            && line - p.line > 10
+        {
+            pending = None;
+        }
+        if let Some((name, from)) = function_head(lang_id, text) {
+            pending = Some(Pending { name, line, from, depth: 0 });
+        }
+
+        // The innermost function this line is part of.
+        let mut owner = innermost(&braces);
+        let mut word_start = None;
+        let bytes = text.as_bytes();
+        for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+            if c.is_alphanumeric() || c == '_' {
+                word_start.get_or_insert(i);
+                continue;
+            }
+            if let Some(start) = word_start.take()
+                && CONTROL.contains(&&text[start..i])
+            {
+                control = true;
+            }
+            match c {
+                '(' => parens += 1,
+                ')' => parens = parens.saturating_sub(1),
+                _ => {}
+            }
+            let in_head = pending.as_ref().is_some_and(|p| p.line < line || i >= p.from);
+            match c {
+                '(' | '[' if in_head => pending.as_mut().unwrap().depth += 1,
+                ')' | ']' if in_head => {
+                    let p = pending.as_mut().unwrap();
+                    p.depth = p.depth.saturating_sub(1);
+                }
+                ';' => {
+                    if in_head && pending.as_ref().unwrap().depth == 0 {
+                        // A declaration without a body.
+                        pending = None;
+                    }
+                    // Go's `if x := f(); x > 0 {` keeps its `if`.
+                    if parens == 0 && lang_id != "go" {
+                        control = false;
+                    }
+                }
+                '=' if in_head && pending.as_ref().unwrap().depth == 0 => {
+                    let prev = i.checked_sub(1).map(|j| bytes[j]);
+                    let next = bytes.get(i + 1).copied();
+                    let operator = matches!(prev, Some(b'=' | b'!' | b'<' | b'>'))
+                        || matches!(next, Some(b'=' | b'>'));
+                    if !operator {
+                        // `fun f() = expr`: no body to measure.
+                        pending = None;
+                    }
+                }
+                '{' => {
+                    if in_head && pending.as_ref().unwrap().depth == 0 {
+                        let p = pending.take().unwrap();
+                        functions.push(FunctionMetrics {
+                            name: p.name,
+                            line: p.line,
+                            complexity: 1,
+                            nesting: 0,
+                            length: 0,
+                        });
+                        braces.push(Brace::Body(functions.len() - 1));
+                        owner = Some(functions.len() - 1);
+                    } else if control {
+                        braces.push(Brace::Control);
+                        if let Some(f) = innermost(&braces) {
+                            let depth = braces
+                                .iter()
+                                .rev()
+                                .take_while(|b| !matches!(b, Brace::Body(_)))
+                                .filter(|b| matches!(b, Brace::Control))
+                                .count();
+                            functions[f].nesting = functions[f].nesting.max(depth);
+                        }
+                    } else {
+                        braces.push(Brace::Other);
+                    }
+                    control = false;
+                }
+                '}' => {
+                    if let Some(Brace::Body(f)) = braces.pop() {
+                        functions[f].length = line - functions[f].line + 1;
+                    }
+                    control = false;
+                }
+                _ => {}
+            }
+        }
+        if let Some(f) = owner {
+            functions[f].complexity += decisions(lang_id, text);
+        }
+    }
+    // Bodies left open at the end of the file run to its last line.
+    let last = masked.lines().count();
+    for f in functions.iter_mut().filter(|f| f.length == 0) {
+        f.length = last - f.line + 1;
+    }
+    functions
+}
+
+fn innermost(braces: &[Brace]) -> Option<usize> {
+    braces.iter().rev().find_map(|b| match b {
+        Brace::Body(f) => Some(*f),
+        _ => None,
+    })
+}
+
+/// An open Python function: its index in the result, the indentation of
+/// its `def` and the indentation of the control blocks open in it.
+struct Frame {
+    index: usize,
+    indent: usize,
+    control: Vec<usize>,
+}
+
+fn measure_indented(lang_id: &str, masked: &str) -> Vec<FunctionMetrics> {
+    let mut functions: Vec<FunctionMetrics> = Vec::new();
+    let mut frames: Vec<Frame> = Vec::new();
+    // Last non-blank line, which ends any function closed by a dedent.
+    let mut last_code = 0;
+    // `(`/`[`/`{` depth: continuation lines don't dedent anything.
+    let mut depth = 0usize;
+
+    for (idx, text) in masked.lines().enumerate() {
+        let line = idx + 1;
+        let trimmed = text.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let indent = text.len() - text.trim_start().len();
+        if depth == 0 {
+            while frames.last().is_some_and(|f| indent <= f.indent) {
+                let f = frames.pop().unwrap();
+                functions[f.index].length = last_code - functions[f.index].line + 1;
+            }
+        }
+        last_code = line;
+
+        if let Some(frame) = frames.last_mut() {
+            functions[frame.index].complexity += decisions(lang_id, text);
+            if depth == 0 {
+                frame.control.retain(|&c| c < indent);
+                let nesting = &mut functions[frame.index].nesting;
+                *nesting = (*nesting).max(frame.control.len());
+            }
+        }
+
+        let first = trimmed
+            .split(|c: char| !c.is_alphanumeric() && c != '_')
+            .next()
+            .unwrap_or("");
+        if depth == 0 {
+            if let Some((name, _)) = function_head(lang_id, text) {
+                functions.push(FunctionMetrics {
+                    name,
+                    line,
+                    complexity: 1,
+                    nesting: 0,
+                    length: 1,
+                });
+                frames.push(Frame { index: functions.len() - 1, indent, control: Vec::new() });
+            } else if CONTROL.contains(&first)
+                && let Some(frame) = frames.last_mut()
+            {
+                frame.control.push(indent);
+            }
+        }
+        for c in trimmed.chars() {
+            match c {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+    for f in frames {
+        functions[f.index].length = last_code - functions[f.index].line + 1;
+    }
+    functions
+}
+
+/// The function a line starts, and where its signature begins.
+fn function_head(lang_id: &str, text: &str) -> Option<(String, usize)> {
+    let trimmed = text.trim_start();
+    let offset = text.len() - trimmed.len();
+    let words = words(trimmed);
+
+    // `fn name`, `def name`, `func (r T) name`, …
+    if let Some(&(pos, kw)) = words.iter().find(|(_, w)| FN_KEYWORDS.contains(w)) {
+        let mut rest = trimmed[pos + kw.len()..].trim_start();
+        rest = rest.strip_prefix('*').unwrap_or(rest).trim_start();
+        if lang_id == "go" && rest.starts_with('(') {
+            // Method receiver.
+            rest = rest[matching_paren(rest)?..].trim_start();
+        }
+        let name = ident_prefix(rest);
+        return (!name.is_empty()).then(|| (name.to_string(), offset + pos));
+    }
+
+    let (&(_, first), _) = words.split_first()?;
+    if is_keyword(first) && !matches!(first, "async" | "static" | "public" | "private" | "protected") {
+        return None;
+    }
+
+    // `type name(params) {` in C-family languages.
+    if C_FAMILY.contains(&lang_id) {
+        let paren = trimmed.find('(')?;
+        let head = trimmed[..paren].trim_end();
+        let start = head
+            .rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == ':' || c == '~'))
+            .map_or(0, |i| i + 1);
+        let (prefix, name) = (&head[..start], &head[start..]);
+        let typed = prefix.trim().chars().all(|c| c.is_alphanumeric() || " _:<>,*&[]~".contains(c));
+        if !name.is_empty() && !prefix.trim().is_empty() && typed && !is_keyword(name.trim_start_matches('~')) {
+            return Some((name.to_string(), offset + start));
+        }
+        return None;
+    }
+
+    // JS/TS class methods (`async name(args) {`) and arrow functions bound
+    // to a name (`const name = (args) => {`).
+    if matches!(lang_id, "javascript" | "typescript" | "jsx" | "tsx") && trimmed.trim_end().ends_with('{') {
+        if let Some(arrow) = trimmed.find("=>")
+            && matches!(first, "const" | "let" | "var" | "export")
+        {
+            let name = words.iter().find(|(_, w)| !matches!(*w, "export" | "const" | "let" | "var"))?.1;
+            return Some((name.to_string(), offset + arrow + 2));
+        }
+        let (pos, name) = *words
+            .iter()
+            .find(|(_, w)| !matches!(*w, "async" | "static" | "get" | "set" | "public" | "private" | "protected"))?;
+        let after = trimmed[pos + name.len()..].trim_start();
+        if after.starts_with('(') && !is_keyword(name) {
+            return Some((name.to_string(), offset + pos));
+        }
+        return None;
+    }
+
+    // Shell `name() {`.
+    if matches!(lang_id, "bash" | "fish") {
+        let name = ident_prefix(trimmed);
+        let after = trimmed[name.len()..].trim_start();
+        if !name.is_empty() && after.starts_with("()") {
+            return Some((name.to_string(), offset));
+        }
+    }
+    None
+}
+
+/// Decision points on one masked line.
+fn decisions(lang_id: &str, text: &str) -> usize {
+    let word_booleans = WORD_BOOLEANS.contains(&lang_id);
+    let mut n = words(text)
+        .iter()
+        .filter(|(_, w)| DECISIONS.contains(w) || (word_booleans && matches!(*w, "and" | "or")))
+        .count();
+    n += text.matches("&&").count() + text.matches(" ? ").count();
+    for (i, _) in text.match_indices("||") {
+        // `|| expr` opening a Rust closure isn't an `or`; one starting a
+        // line continues a condition.
+        let before = text[..i].trim_end();
+        let closure = lang_id == "rust"
+            && (before.ends_with(['(', ',', '=', '{']) || before.ends_with("move"));
+        if !closure {
+            n += 1;
+        }
+    }
+    if lang_id == "rust" {
+        // Match arms, except the catch-all.
+        n += text
+            .match_indices("=>")
+            .filter(|(i, _)| !text[..*i].trim_end().ends_with('_'))
+            .count();
+    }
+    n
+}
+
+/// Identifier-like words with their byte offsets.
+fn words(text: &str) -> Vec<(usize, &str)> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (i, c) in text.char_indices().chain(std::iter::once((text.len(), ' '))) {
+        if c.is_alphanumeric() || c == '_' {
+            start.get_or_insert(i);
+        } else if let Some(s) = start.take() {
+            out.push((s, &text[s..i]));
+        }
+    }
+    out
+}
+
+fn ident_prefix(s: &str) -> &str {
+    let end = s
+        .find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '$'))
+        .unwrap_or(s.len());
+    &s[..end]
+}
+
+/// Byte offset just past the `)` matching the `(` that opens `s`.
+fn matching_paren(s: &str) -> Option<usize> {
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Words that can start a statement but never a function head.
+fn is_keyword(word: &str) -> bool {
+    CONTROL.contains(&word)
+        || matches!(
+            word,
+            "return" | "new" | "throw" | "delete" | "typedef" | "using" | "goto" | "sizeof"
+                | "lock" | "fixed" | "synchronized" | "await" | "yield" | "async" | "static"
+                | "public" | "private" | "protected" | "case" | "default" | "class" | "struct"
+        )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FunctionMetrics, measure};
+    use crate::lang;
+    use crate::lexer::{self, Syntax};
+    use std::path::Path;
+
+    fn metrics(name: &str, source: &str) -> Vec<(String, usize, usize, usize, usize)> {
+        let language = lang::detect(Path::new(name), Some(source)).unwrap();
+        let scan = lexer::scan(source, &Syntax::for_language(Some(language)));
+        measure(language.id, &scan.code)
+            .into_iter()
+            .map(|FunctionMetrics { name, line, complexity, nesting, length }| {
+                (name, line, complexity, nesting, length)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_measure() {
+        let rust = r#"
+fn plain() -> u8 { 1 }
+
+/// if this { were code } it would count
+pub(crate) fn branchy(x: Option<u8>, v: &[u8; 4]) -> u8 {
+    let s = "if { while";
+    if let Some(n) = x && n > 2 {
+        for b in v {
+            match b {
+                0 => return 0,
+                1 | 2 => continue,
+                _ => {}
+            }
+        }
+    } else if x.is_none() || v.is_empty() {
+        return v.iter().map(|| 1).count() as u8;
+    }
+    0
+}
+
+trait T { fn decl(&self); }
+"#;
+        assert_eq!(
+            metrics("lib.rs", rust),
+            [("plain".to_string(), 2, 1, 0, 1), ("branchy".to_string(), 5, 8, 3, 15)]
+        );
+
+        let python = "def outer(a,\n          b):\n    '''if docstring'''\n    if a and b:\n        for x in a:\n            pass\n    def inner():\n        return 1 if a else 2\n    return 0\n\nx = 1\n";
+        assert_eq!(
+            metrics("m.py", python),
+            [("outer".to_string(), 1, 4, 2, 9), ("inner".to_string(), 7, 2, 0, 2)]
+        );
+
+        let c = "static int add(int a, int b);\n\nint Widget::draw(int n) {\n    while (n--) {\n        switch (n) { case 1: break; default: n = n ? 1 : 0; }\n    }\n    return 0;\n}\n";
+        assert_eq!(metrics("w.cpp", c), [("Widget::draw".to_string(), 3, 4, 2, 6)]);
+
+        let go = "package p\nfunc (s *S) Get(k string) (int, bool) {\n\tif v, ok := s.m[k]; ok {\n\t\treturn v, true\n\t}\n\treturn 0, false\n}\n";
+        assert_eq!(metrics("s.go", go), [("Get".to_string(), 2, 2, 1, 6)]);
+    }
+}
@@ -0,0 +1,228 @@
+//! File content sniffing: tells binaries from text and decodes text that is
+//! not UTF-8 (UTF-16 with or without a BOM, Latin-1) instead of dropping it.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Bytes inspected when deciding whether a file is binary.
+const SNIFF_LEN: usize = 8 * 1024;
+
+/// Text encoding detected for a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Utf8,
+    /// UTF-8 with a byte-order mark (stripped when decoding).
+    Utf8Bom,
+    Utf16Le,
+    Utf16Be,
+    /// Not valid UTF-8; every byte is taken as its Latin-1 code point, which
+    /// never fails and round-trips.
+    Latin1,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Utf8Bom => "utf-8-bom",
+            Encoding::Utf16Le => "utf-16le",
+            Encoding::Utf16Be => "utf-16be",
+            Encoding::Latin1 => "latin-1",
+        }
+    }
+
+    /// Whether the tree should point the encoding out.  Plain UTF-8 (with or
+    /// without a BOM) is the unremarkable case.
+    pub fn is_notable(self) -> bool {
+        !matches!(self, Encoding::Utf8 | Encoding::Utf8Bom)
+    }
+}
+
+/// What a file turned out to contain.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Content {
+    Binary,
+    Text { text: String, encoding: Encoding },
+}
+
+/// Classify a sample without decoding it.
+pub fn sniff(bytes: &[u8]) -> Option<Encoding> {
+    let sample = &bytes[..bytes.len().min(SNIFF_LEN)];
+    if sample.starts_with(&[0xEF, 0xBB, 0xBF]) {
+        return Some(Encoding::Utf8Bom);
+    }
+    if sample.starts_with(&[0xFF, 0xFE]) {
+        return Some(Encoding::Utf16Le);
+    }
+    if sample.starts_with(&[0xFE, 0xFF]) {
+        return Some(Encoding::Utf16Be);
+    }
+    if sample.contains(&0) {
+        // BOM-less UTF-16 of mostly-ASCII text has a NUL in every other byte.
+        return utf16_without_bom(sample);
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => Some(Encoding::Utf8),
+        // A multi-byte sequence cut off by the end of the sample is fine.
+        Err(e) if e.error_len().is_none() => Some(Encoding::Utf8),
+        Err(_) if looks_like_text(sample) => Some(Encoding::Latin1),
+        Err(_) => None,
+    }
+}
+
+fn utf16_without_bom(sample: &[u8]) -> Option<Encoding> {
+    let pairs = sample.len() / 2;
+    if pairs == 0 {
+        return None;
+    }
+    let zero_at = |parity: usize| {
+        sample.chunks_exact(2).filter(|p| p[parity] == 0).count()
+    };
+    let (odd, even) = (zero_at(1), zero_at(0));
+    // Require nearly every pair to look alike, and no NUL pairs at all.
+    let nul_pairs = sample.chunks_exact(2).filter(|p| p == &[0, 0]).count();
+    if nul_pairs > 0 {
+        return None;
+    }
+    if odd * 10 >= pairs * 9 {
+        Some(Encoding::Utf16Le)
+    } else if even * 10 >= pairs * 9 {
+        Some(Encoding::Utf16Be)
+    } else {
+        None
+    }
+}
+
+/// Legacy 8-bit text has few control characters besides whitespace.
+fn looks_like_text(sample: &[u8]) -> bool {
+    let control = sample
+        .iter()
+        .filter(|&&b| b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B))
+        .count();
+    control * 100 <= sample.len()
+}
+
+/// Decode `bytes` according to what `sniff` finds.
+pub fn decode(bytes: &[u8]) -> Content {
+    let Some(encoding) = sniff(bytes) else {
+        return Content::Binary;
+    };
+    let text = match encoding {
+        Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+        Encoding::Utf8Bom => String::from_utf8_lossy(&bytes[3..]).into_owned(),
+        Encoding::Utf16Le | Encoding::Utf16Be => {
+            let body = if bytes.starts_with(&[0xFF, 0xFE]) || bytes.starts_with(&[0xFE, 0xFF]) {
+                &bytes[2..]
+            } else {
+                bytes
+            };
+            let units = body.chunks_exact(2).map(|p| match encoding {
+                Encoding::Utf16Le => u16::from_le_bytes([p[0], p[1]]),
+                _ => u16::from_be_bytes([p[0], p[1]]),
+            });
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        Encoding::Latin1 => bytes.iter().map(|&b| b as char).collect(),
+    };
+    Content::Text { text, encoding }
+}
+
+/// Read and decode a whole file.
+pub fn read(path: &Path) -> io::Result<Content> {
+    Ok(decode(&std::fs::read(path)?))
+}
+
+/// Decoded text of a file, `None` if it is unreadable or binary.
+pub fn read_text(path: &Path) -> Option<String> {
+    match read(path).ok()? {
+        Content::Text { text, .. } => Some(text),
+        Content::Binary => None,
+    }
+}
+
+/// Count lines with `str::lines()` semantics (a final line without a newline
+/// still counts) without holding the file in memory.  Returns `None` for
+/// binaries.  UTF-16 files are decoded whole since their newlines are two
+/// bytes wide.
+pub fn count_lines(path: &Path) -> io::Result<Option<usize>> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut count = 0;
+    let mut last_byte = None;
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        let chunk = &buf[..n];
+        if last_byte.is_none() {
+            match sniff(chunk) {
+                None => return Ok(None),
+                Some(Encoding::Utf16Le | Encoding::Utf16Be) => {
+                    let mut bytes = chunk.to_vec();
+                    file.read_to_end(&mut bytes)?;
+                    return Ok(match decode(&bytes) {
+                        Content::Text { text, .. } => Some(text.lines().count()),
+                        Content::Binary => None,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+        count += chunk.iter().filter(|&&b| b == b'\n').count();
+        last_byte = Some(chunk[n - 1]);
+    }
+    Ok(Some(count + usize::from(last_byte.is_some_and(|b| b != b'\n'))))
+}
+
+/// Sniff only the first few KiB of a file; cheap enough for `--text-only`.
+pub fn is_binary_file(path: &Path) -> bool {
+    let mut sample = Vec::with_capacity(SNIFF_LEN);
+    match File::open(path).and_then(|f| f.take(SNIFF_LEN as u64).read_to_end(&mut sample)) {
+        Ok(_) => sniff(&sample).is_none(),
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Content, Encoding, count_lines, decode};
+
+    fn text(s: &str, encoding: Encoding) -> Content {
+        Content::Text { text: s.to_string(), encoding }
+    }
+
+    #[test]
+    fn test_decode() {
+        assert_eq!(decode(b"fn main() {}\n"), text("fn main() {}\n", Encoding::Utf8));
+        assert_eq!(decode(b"\xEF\xBB\xBFhi"), text("hi", Encoding::Utf8Bom));
+        assert_eq!(decode(b"\xFF\xFEh\0i\0"), text("hi", Encoding::Utf16Le));
+        assert_eq!(decode(b"\xFE\xFF\0h\0i"), text("hi", Encoding::Utf16Be));
+        assert_eq!(decode(b"h\0i\0!\0\n\0"), text("hi!\n", Encoding::Utf16Le));
+        assert_eq!(decode(b"caf\xE9\n"), text("café\n", Encoding::Latin1));
+        assert_eq!(decode(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"), Content::Binary);
+        assert_eq!(decode(b"\x7FELF\x02\x01\x01\x00\x00\x00"), Content::Binary);
+        assert_eq!(decode(b"\x01\x02\x03\x04\xff\xfe\x80"), Content::Binary);
+        assert_eq!(decode(b""), text("", Encoding::Utf8));
+    }
+
+    #[test]
+    fn test_count_lines_matches_str_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("f");
+        // Long enough to span several read chunks.
+        let long = "x".repeat(100_000) + "\n" + &"y\n".repeat(70_000) + "tail";
+        let cases = ["", "\n", "a", "a\n", "a\nb", "a\r\nb\r\n", "\n\n", long.as_str()];
+        for case in cases {
+            std::fs::write(&path, case).unwrap();
+            assert_eq!(count_lines(&path).unwrap(), Some(case.lines().count()), "{:?}", &case[..case.len().min(10)]);
+        }
+        std::fs::write(&path, b"\xFF\xFEa\0\n\0b\0").unwrap();
+        assert_eq!(count_lines(&path).unwrap(), Some(2));
+        std::fs::write(&path, b"\x7FELF\0\0\0\0").unwrap();
+        assert_eq!(count_lines(&path).unwrap(), None);
+    }
+}
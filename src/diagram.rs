@@ -0,0 +1,286 @@
+//! Graph diagram output: Graphviz DOT (`--format dot`) and Mermaid (`--format mermaid`).
+//!
+//! Every entry becomes a graph node with an edge from its parent directory.
+//! Directories with at least `cluster_min` entries are additionally wrapped in
+//! a cluster/subgraph box so large folders stay readable.  Theme colors are
+//! meant for dark terminals, so both formats use a dark background.
+
+use crate::config::ThemeColors;
+use crate::style::hex_color;
+use crate::tree::{Node, NodeKind, human_size};
+
+pub struct DiagramOptions {
+    pub show_sizes: bool,
+    pub show_lines: bool,
+    /// Draw directories with at least this many entries as clusters.
+    pub cluster_min: Option<usize>,
+}
+
+// ── Graphviz DOT ──────────────────────────────────────────────────────────────
+
+pub fn to_dot(root: &Node, opts: &DiagramOptions, colors: &ThemeColors) -> String {
+    let mut out = String::new();
+    out.push_str("digraph ssp {\n");
+    out.push_str("  rankdir=LR;\n");
+    out.push_str("  bgcolor=\"#1e1e1e\";\n");
+    out.push_str("  node [shape=box, style=rounded, fontname=\"monospace\"];\n");
+    out.push_str("  edge [arrowhead=none, color=\"#8a8a8a\"];\n");
+    let mut next_id = 0usize;
+    dot_node(root, opts, colors, &mut next_id, 1, &mut out);
+    out.push_str("}\n");
+    out
+}
+
+/// Emit `node` and its subtree; returns the id assigned to `node`.
+fn dot_node(
+    node: &Node,
+    opts: &DiagramOptions,
+    colors: &ThemeColors,
+    next_id: &mut usize,
+    depth: usize,
+    out: &mut String,
+) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let indent = "  ".repeat(depth);
+
+    let color = hex_color(kind_color(node, colors)).unwrap_or("black");
+    let shape = if node.kind == NodeKind::Dir { "folder" } else { "box" };
+    out.push_str(&format!(
+        "{}n{} [label=\"{}\", shape={}, color=\"{}\", fontcolor=\"{}\"];\n",
+        indent,
+        id,
+        dot_escape(&label(node, opts)),
+        shape,
+        color,
+        color
+    ));
+
+    if node.kind != NodeKind::Dir || node.children.is_empty() {
+        return id;
+    }
+
+    let cluster = is_cluster(node, opts);
+    let child_indent = if cluster {
+        out.push_str(&format!("{}subgraph cluster_n{} {{\n", indent, id));
+        out.push_str(&format!(
+            "{}  label=\"{}\";\n{}  style=dashed;\n{}  color=\"{}\";\n",
+            indent,
+            dot_escape(node.name()),
+            indent,
+            indent,
+            color
+        ));
+        depth + 1
+    } else {
+        depth
+    };
+
+    let mut edges = Vec::new();
+    for child in &node.children {
+        let child_id = dot_node(child, opts, colors, next_id, child_indent, out);
+        edges.push(child_id);
+    }
+    if cluster {
+        out.push_str(&format!("{}}}\n", indent));
+    }
+    for child_id in edges {
+        out.push_str(&format!("{}n{} -> n{};\n", indent, id, child_id));
+    }
+    id
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+// ── Mermaid ───────────────────────────────────────────────────────────────────
+
+pub fn to_mermaid(root: &Node, opts: &DiagramOptions, colors: &ThemeColors) -> String {
+    let mut out = String::new();
+    out.push_str("graph LR\n");
+    let mut next_id = 0usize;
+    let mut classes: Vec<(usize, &'static str)> = Vec::new();
+    mermaid_node(root, opts, &mut next_id, &mut classes, 1, &mut out);
+
+    for (class, color_name) in [
+        ("dir", &colors.dir),
+        ("file", &colors.file),
+        ("exec", &colors.exec),
+        ("symlink", &colors.symlink),
+    ] {
+        if let Some(hex) = hex_color(color_name) {
+            out.push_str(&format!(
+                "  classDef {} fill:#1e1e1e,color:{},stroke:{};\n",
+                class, hex, hex
+            ));
+        }
+    }
+    for class in ["dir", "file", "exec", "symlink"] {
+        let ids: Vec<String> = classes
+            .iter()
+            .filter(|(_, c)| *c == class)
+            .map(|(id, _)| format!("n{}", id))
+            .collect();
+        if !ids.is_empty() {
+            out.push_str(&format!("  class {} {};\n", ids.join(","), class));
+        }
+    }
+    out
+}
+
+fn mermaid_node(
+    node: &Node,
+    opts: &DiagramOptions,
+    next_id: &mut usize,
+    classes: &mut Vec<(usize, &'static str)>,
+    depth: usize,
+    out: &mut String,
+) -> usize {
+    let id = *next_id;
+    *next_id += 1;
+    let indent = "  ".repeat(depth);
+
+    let text = mermaid_escape(&label(node, opts));
+    let shape = match node.kind {
+        NodeKind::Dir => format!("n{}[\"{}\"]", id, text),
+        _ => format!("n{}(\"{}\")", id, text),
+    };
+    out.push_str(&format!("{}{}\n", indent, shape));
+    classes.push((id, kind_class(node)));
+
+    if node.kind != NodeKind::Dir || node.children.is_empty() {
+        return id;
+    }
+
+    let cluster = is_cluster(node, opts);
+    let child_indent = if cluster {
+        out.push_str(&format!(
+            "{}subgraph g{} [\"{}\"]\n",
+            indent,
+            id,
+            mermaid_escape(node.name())
+        ));
+        depth + 1
+    } else {
+        depth
+    };
+
+    let mut edges = Vec::new();
+    for child in &node.children {
+        edges.push(mermaid_node(child, opts, next_id, classes, child_indent, out));
+    }
+    if cluster {
+        out.push_str(&format!("{}end\n", indent));
+    }
+    for child_id in edges {
+        out.push_str(&format!("{}n{} --> n{}\n", indent, id, child_id));
+    }
+    id
+}
+
+/// Mermaid decodes `#…;` entity codes inside labels, so `#` itself must be
+/// escaped before the quotes are.
+fn mermaid_escape(s: &str) -> String {
+    s.replace('#', "#35;").replace('"', "#quot;")
+}
+
+// ── Shared helpers ────────────────────────────────────────────────────────────
+
+fn is_cluster(node: &Node, opts: &DiagramOptions) -> bool {
+    opts.cluster_min.is_some_and(|min| node.children.len() >= min)
+}
+
+fn label(node: &Node, opts: &DiagramOptions) -> String {
+    let mut label = node.name().to_string();
+    if node.kind == NodeKind::Dir {
+        label.push('/');
+    }
+    let mut meta: Vec<String> = Vec::new();
+    if opts.show_sizes && node.kind != NodeKind::Dir {
+        meta.push(human_size(node.size));
+    }
+    if opts.show_lines
+        && node.kind == NodeKind::File
+        && let Some(lines) = node.lines
+    {
+        meta.push(format!("{} lines", lines));
+    }
+    if !meta.is_empty() {
+        label.push_str(&format!(" ({})", meta.join(", ")));
+    }
+    label
+}
+
+fn kind_class(node: &Node) -> &'static str {
+    match node.kind {
+        NodeKind::Dir => "dir",
+        NodeKind::Symlink => "symlink",
+        NodeKind::File if node.is_exec => "exec",
+        NodeKind::File => "file",
+    }
+}
+
+fn kind_color<'a>(node: &Node, colors: &'a ThemeColors) -> &'a str {
+    match kind_class(node) {
+        "dir" => &colors.dir,
+        "symlink" => &colors.symlink,
+        "exec" => &colors.exec,
+        _ => &colors.file,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DiagramOptions, to_dot, to_mermaid};
+    use crate::config::ThemeColors;
+    use crate::tree::{Node, NodeKind, test_node};
+
+    fn sample() -> Node {
+        let quoted = test_node("/r/sub/say \"hi\" [1] #2.txt", NodeKind::File, 2048, vec![]);
+        let mut plain = test_node("/r/sub/b.txt", NodeKind::File, 1, vec![]);
+        plain.lines = Some(3);
+        let sub = test_node("/r/sub", NodeKind::Dir, 0, vec![quoted, plain]);
+        let mut run = test_node("/r/run", NodeKind::File, 10, vec![]);
+        run.is_exec = true;
+        test_node("/r", NodeKind::Dir, 0, vec![sub, run])
+    }
+
+    fn opts(cluster_min: Option<usize>) -> DiagramOptions {
+        DiagramOptions { show_sizes: true, show_lines: false, cluster_min }
+    }
+
+    #[test]
+    fn test_dot() {
+        let dot = to_dot(&sample(), &opts(Some(2)), &ThemeColors::default());
+        assert!(dot.contains("n2 [label=\"say \\\"hi\\\" [1] #2.txt (2.0K)\", shape=box"));
+        assert!(dot.contains("n4 [label=\"run (10B)\", shape=box, color=\"#0dbc79\""));
+        // Both directories have two entries, so `sub`'s cluster nests in the root's.
+        let outer = dot.find("  subgraph cluster_n0 {\n").unwrap();
+        let inner = dot.find("    subgraph cluster_n1 {\n").unwrap();
+        assert!(outer < inner);
+        assert!(dot.contains("    }\n    n1 -> n2;\n    n1 -> n3;\n"));
+        assert!(dot.ends_with("  }\n  n0 -> n1;\n  n0 -> n4;\n}\n"));
+
+        let flat = to_dot(&sample(), &opts(Some(3)), &ThemeColors::default());
+        assert!(!flat.contains("subgraph"));
+
+        // Only files with a line count get one.
+        let with_lines = DiagramOptions { show_lines: true, ..opts(None) };
+        let dot = to_dot(&sample(), &with_lines, &ThemeColors::default());
+        assert!(dot.contains("n3 [label=\"b.txt (1B, 3 lines)\""));
+        assert!(dot.contains("n4 [label=\"run (10B)\""));
+    }
+
+    #[test]
+    fn test_mermaid() {
+        let mmd = to_mermaid(&sample(), &opts(Some(2)), &ThemeColors::default());
+        assert!(mmd.starts_with("graph LR\n  n0[\"r/\"]\n  subgraph g0 [\"r\"]\n    n1[\"sub/\"]\n"));
+        assert!(mmd.contains("      n2(\"say #quot;hi#quot; [1] #35;2.txt (2.0K)\")\n"));
+        assert!(mmd.contains("    end\n    n1 --> n2\n    n1 --> n3\n"));
+        assert!(mmd.contains("  end\n  n0 --> n1\n  n0 --> n4\n"));
+        assert!(mmd.contains("  classDef dir fill:#1e1e1e,color:#2472c8,stroke:#2472c8;\n"));
+        assert!(mmd.contains("  classDef exec fill:#1e1e1e,color:#0dbc79,stroke:#0dbc79;\n"));
+        assert!(mmd.ends_with("  class n0,n1 dir;\n  class n2,n3 file;\n  class n4 exec;\n"));
+    }
+}
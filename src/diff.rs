@@ -0,0 +1,390 @@
+//! `ssp diff OLD NEW`: compare two trees (live directories or JSON snapshots)
+//! and render a merged tree with added / removed / changed markers.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use owo_colors::OwoColorize;
+use serde::Serialize;
+
+use crate::cli::HashAlgo;
+use crate::hash;
+use crate::output;
+use crate::style::{Palette, StyleConfig, paint_connector, paint_meta};
+use crate::tree::{Node, NodeKind, human_size};
+use crate::walk::{self, WalkOptions};
+
+/// What happened to an entry between the two trees.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Change {
+    Added,
+    Removed,
+    Modified,
+    Unchanged,
+}
+
+impl Change {
+    pub fn glyph(self) -> char {
+        match self {
+            Change::Added => '+',
+            Change::Removed => '-',
+            Change::Modified => '~',
+            Change::Unchanged => ' ',
+        }
+    }
+}
+
+/// Which properties count as a modification (size is always compared).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CompareOptions {
+    pub mtime: bool,
+    /// Compare file bytes when sizes match (only possible for live trees).
+    pub content: bool,
+}
+
+/// One entry of the merged tree.
+#[derive(Debug)]
+pub struct DiffNode<'a> {
+    pub name: String,
+    pub rel: String,
+    pub kind: NodeKind,
+    pub change: Change,
+    /// Which properties differ, for `Modified` entries.
+    pub reasons: Vec<&'static str>,
+    pub old: Option<&'a Node>,
+    pub new: Option<&'a Node>,
+    pub children: Vec<DiffNode<'a>>,
+}
+
+/// Load one side of a diff: a directory is walked (and hashed with `--hash`),
+/// a file is read as a snapshot.
+pub fn load_side(path: &Path, opts: &WalkOptions, hash: Option<HashAlgo>) -> Result<Node, String> {
+    if path.is_dir() {
+        let mut root = walk::build_tree(path, opts);
+        if let Some(algo) = hash {
+            hash::hash_tree(&mut root, algo);
+        }
+        return Ok(root);
+    }
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("cannot read '{}': {}", path.display(), e))?;
+    let (root, _) = output::from_json(&text)
+        .map_err(|e| format!("invalid snapshot '{}': {}", path.display(), e))?;
+    Ok(walk::filter_tree(root, opts))
+}
+
+/// Merge two trees by entry name.
+pub fn diff_trees<'a>(old: &'a Node, new: &'a Node, cmp: CompareOptions) -> DiffNode<'a> {
+    let mut root = merge(old.name().to_string(), String::new(), Some(old), Some(new), cmp);
+    root.name = format!("{} → {}", old.name(), new.name());
+    root
+}
+
+/// Matching entries from the old and new tree.
+type Pair<'a> = (Option<&'a Node>, Option<&'a Node>);
+
+fn merge<'a>(
+    name: String,
+    rel: String,
+    old: Option<&'a Node>,
+    new: Option<&'a Node>,
+    cmp: CompareOptions,
+) -> DiffNode<'a> {
+    let kind = new.or(old).map(|n| n.kind).unwrap_or(NodeKind::File);
+
+    // Pair children by name; a kind change counts as remove + add.
+    let mut pairs: BTreeMap<(bool, String), Pair> = BTreeMap::new();
+    for child in old.map(|n| n.children.as_slice()).unwrap_or_default() {
+        let key = (child.kind != NodeKind::Dir, child.name().to_string());
+        pairs.entry(key).or_default().0 = Some(child);
+    }
+    for child in new.map(|n| n.children.as_slice()).unwrap_or_default() {
+        let key = (child.kind != NodeKind::Dir, child.name().to_string());
+        pairs.entry(key).or_default().1 = Some(child);
+    }
+    let children: Vec<DiffNode> = pairs
+        .into_iter()
+        .map(|((_, child_name), (o, n))| {
+            let child_rel = if rel.is_empty() {
+                child_name.clone()
+            } else {
+                format!("{}/{}", rel, child_name)
+            };
+            merge(child_name, child_rel, o, n, cmp)
+        })
+        .collect();
+
+    let (change, reasons) = match (old, new) {
+        (None, Some(_)) => (Change::Added, Vec::new()),
+        (Some(_), None) => (Change::Removed, Vec::new()),
+        (Some(_), Some(_)) if kind == NodeKind::Dir => {
+            let changed = children.iter().any(|c| c.change != Change::Unchanged);
+            (if changed { Change::Modified } else { Change::Unchanged }, Vec::new())
+        }
+        (Some(o), Some(n)) => {
+            let reasons = compare_entries(o, n, cmp);
+            let change = if reasons.is_empty() { Change::Unchanged } else { Change::Modified };
+            (change, reasons)
+        }
+        (None, None) => (Change::Unchanged, Vec::new()),
+    };
+
+    DiffNode { name, rel, kind, change, reasons, old, new, children }
+}
+
+fn compare_entries(old: &Node, new: &Node, cmp: CompareOptions) -> Vec<&'static str> {
+    let mut reasons = Vec::new();
+    if old.size != new.size {
+        reasons.push("size");
+    }
+    if cmp.mtime && old.mtime != new.mtime {
+        reasons.push("mtime");
+    }
+    // Digests from `--hash` (live or stored in a snapshot) settle content equality.
+    if let (Some(a), Some(b)) = (&old.hash, &new.hash)
+        && reasons.is_empty()
+        && a != b
+    {
+        reasons.push("content");
+    }
+    if cmp.content && reasons.is_empty() && old.kind == NodeKind::File && !same_content(old, new) {
+        reasons.push("content");
+    }
+    reasons
+}
+
+/// Byte comparison of two files; unreadable files (e.g. from a snapshot) compare equal.
+fn same_content(old: &Node, new: &Node) -> bool {
+    match (std::fs::read(&old.path), std::fs::read(&new.path)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => true,
+    }
+}
+
+// ── Summary ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Default, Serialize)]
+pub struct DiffSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+    pub unchanged: usize,
+    pub old_size: u64,
+    pub new_size: u64,
+}
+
+impl DiffSummary {
+    pub fn from_diff(root: &DiffNode) -> Self {
+        let mut s = Self::default();
+        s.collect(root);
+        s
+    }
+
+    fn collect(&mut self, node: &DiffNode) {
+        for child in &node.children {
+            if child.kind != NodeKind::Dir {
+                match child.change {
+                    Change::Added => self.added += 1,
+                    Change::Removed => self.removed += 1,
+                    Change::Modified => self.modified += 1,
+                    Change::Unchanged => self.unchanged += 1,
+                }
+                self.old_size += child.old.map(|n| n.size).unwrap_or(0);
+                self.new_size += child.new.map(|n| n.size).unwrap_or(0);
+            }
+            self.collect(child);
+        }
+    }
+
+    pub fn format(&self) -> String {
+        let delta = self.new_size as i64 - self.old_size as i64;
+        let sign = if delta < 0 { "-" } else { "+" };
+        format!(
+            "\n{} added, {} removed, {} changed, {} unchanged files ({}{})",
+            self.added,
+            self.removed,
+            self.modified,
+            self.unchanged,
+            sign,
+            human_size(delta.unsigned_abs())
+        )
+    }
+}
+
+// ── JSON ─────────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Serialize)]
+pub struct JsonDiff {
+    pub ssp_version: String,
+    pub old: String,
+    pub new: String,
+    pub summary: DiffSummary,
+    /// Every added, removed or modified entry (unchanged ones are omitted).
+    pub changes: Vec<JsonChange>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct JsonChange {
+    pub path: String,
+    #[serde(rename = "type")]
+    pub kind: &'static str,
+    pub change: Change,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub reasons: Vec<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_size: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_size: Option<u64>,
+}
+
+pub fn to_json(root: &DiffNode, old: &Path, new: &Path) -> JsonDiff {
+    let mut changes = Vec::new();
+    collect_changes(root, &mut changes);
+    JsonDiff {
+        ssp_version: env!("CARGO_PKG_VERSION").to_string(),
+        old: old.to_string_lossy().into_owned(),
+        new: new.to_string_lossy().into_owned(),
+        summary: DiffSummary::from_diff(root),
+        changes,
+    }
+}
+
+fn collect_changes(node: &DiffNode, out: &mut Vec<JsonChange>) {
+    for child in &node.children {
+        // Directories are reported only when they appear or disappear as a whole.
+        let report = match child.change {
+            Change::Unchanged => false,
+            Change::Modified => child.kind != NodeKind::Dir,
+            Change::Added | Change::Removed => true,
+        };
+        if report {
+            out.push(JsonChange {
+                path: child.rel.clone(),
+                kind: child.kind.as_str(),
+                change: child.change,
+                reasons: child.reasons.clone(),
+                old_size: child.old.map(|n| n.total_size()),
+                new_size: child.new.map(|n| n.total_size()),
+            });
+        }
+        collect_changes(child, out);
+    }
+}
+
+// ── Tree rendering ────────────────────────────────────────────────────────────
+
+pub struct DiffRenderOptions {
+    pub show_sizes: bool,
+    /// Hide unchanged entries (ancestors of changes stay visible).
+    pub changes_only: bool,
+}
+
+pub fn render_diff(
+    root: &DiffNode,
+    style: &StyleConfig,
+    palette: &Palette,
+    opts: &DiffRenderOptions,
+) -> String {
+    let mut out = format!("{}\n", root.name);
+    let children = visible_children(root, opts);
+    for (i, child) in children.iter().enumerate() {
+        render_node(child, "", i == children.len() - 1, style, palette, opts, &mut out);
+    }
+    out
+}
+
+fn visible_children<'n, 'a>(node: &'n DiffNode<'a>, opts: &DiffRenderOptions) -> Vec<&'n DiffNode<'a>> {
+    node.children
+        .iter()
+        .filter(|c| !opts.changes_only || c.change != Change::Unchanged)
+        .collect()
+}
+
+fn render_node(
+    node: &DiffNode,
+    prefix: &str,
+    is_last: bool,
+    style: &StyleConfig,
+    palette: &Palette,
+    opts: &DiffRenderOptions,
+    out: &mut String,
+) {
+    let connector = if is_last { style.branch_last() } else { style.branch_mid() };
+    let change_style = match node.change {
+        Change::Added => palette.diff_added,
+        Change::Removed => palette.diff_removed,
+        Change::Modified => palette.diff_changed,
+        Change::Unchanged => palette.file,
+    };
+    let name_style = if node.change == Change::Unchanged && node.kind == NodeKind::Dir {
+        palette.dir
+    } else {
+        change_style
+    };
+    let slash = if node.kind == NodeKind::Dir { "/" } else { "" };
+
+    let mut meta = String::new();
+    if opts.show_sizes && node.kind != NodeKind::Dir {
+        let text = match (node.old, node.new) {
+            (Some(o), Some(n)) if o.size != n.size => {
+                format!("({} → {})", human_size(o.size), human_size(n.size))
+            }
+            (_, Some(n)) => format!("({})", human_size(n.size)),
+            (Some(o), None) => format!("({})", human_size(o.size)),
+            (None, None) => String::new(),
+        };
+        meta = format!(" {}", paint_meta(&text, palette));
+    }
+    if node.change == Change::Modified && node.kind != NodeKind::Dir && !node.reasons.is_empty() {
+        meta.push_str(&format!(" {}", paint_meta(&format!("[{}]", node.reasons.join(", ")), palette)));
+    }
+
+    out.push_str(&format!(
+        "{}{}{} {}{}\n",
+        paint_connector(prefix, palette),
+        paint_connector(connector, palette),
+        node.change.glyph().style(change_style),
+        format!("{}{}", node.name, slash).style(name_style),
+        meta
+    ));
+
+    let segment = if is_last { style.branch_blank() } else { style.branch_pipe() };
+    let new_prefix = format!("{}{}", prefix, segment);
+    let children = visible_children(node, opts);
+    for (i, child) in children.iter().enumerate() {
+        render_node(child, &new_prefix, i == children.len() - 1, style, palette, opts, out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Change, CompareOptions, DiffSummary, diff_trees};
+    use crate::tree::{NodeKind, test_node as node};
+
+    #[test]
+    fn test_diff_trees() {
+        let old = node("/a", NodeKind::Dir, 0, vec![
+            node("/a/keep.txt", NodeKind::File, 1, vec![]),
+            node("/a/grow.txt", NodeKind::File, 1, vec![]),
+            node("/a/gone.txt", NodeKind::File, 5, vec![]),
+        ]);
+        let new = node("/b", NodeKind::Dir, 0, vec![
+            node("/b/keep.txt", NodeKind::File, 1, vec![]),
+            node("/b/grow.txt", NodeKind::File, 3, vec![]),
+            node("/b/sub", NodeKind::Dir, 0, vec![node("/b/sub/new.txt", NodeKind::File, 2, vec![])]),
+        ]);
+
+        let diff = diff_trees(&old, &new, CompareOptions::default());
+        assert_eq!(diff.change, Change::Modified);
+        let change_of = |name: &str| diff.children.iter().find(|c| c.name == name).unwrap().change;
+        assert_eq!(change_of("keep.txt"), Change::Unchanged);
+        assert_eq!(change_of("grow.txt"), Change::Modified);
+        assert_eq!(change_of("gone.txt"), Change::Removed);
+        assert_eq!(change_of("sub"), Change::Added);
+
+        let summary = DiffSummary::from_diff(&diff);
+        assert_eq!((summary.added, summary.removed, summary.modified, summary.unchanged), (1, 1, 1, 1));
+        assert_eq!((summary.old_size, summary.new_size), (7, 6));
+    }
+}
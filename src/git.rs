@@ -1,9 +1,15 @@
-//! Git status integration: per-path status via `git status --porcelain`.
-//! Gracefully degrades if git is not installed or the dir is not a repo.
+//! Git status integration: per-path status via `git status --porcelain`,
+//! line authors via `git blame`, and past trees read straight from the
+//! object store for `ssp stats`.
+//! Status and blame gracefully degrade if git is not installed or the dir is
+//! not a repo.
 
 use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
+use std::process::{Command, Stdio};
+
+use serde::Serialize;
 
 /// Key: canonical path (as reported by git, relative to repo root joined with root).
 /// Value: [index_byte, worktree_byte].
@@ -37,9 +43,7 @@ pub fn collect_status(dir: &Path) -> Option<GitStatusMap> {
     let mut map = GitStatusMap::new();
     // Porcelain v1 with -z: entries separated by NUL. Each entry is "XY path".
     let stdout = output.stdout;
-    let mut iter = stdout.split(|&b| b == 0).peekable();
-
-    while let Some(entry) = iter.next() {
+    for entry in stdout.split(|&b| b == 0) {
         if entry.len() < 4 {
             continue;
         }
@@ -56,3 +60,174 @@ pub fn collect_status(dir: &Path) -> Option<GitStatusMap> {
 
     Some(map)
 }
+
+/// Author of each of `lines` (1-based) of `file`, via `git blame`.
+/// Returns `None` if git is unavailable or the file isn't tracked; lines
+/// not yet committed are attributed to "Not Committed Yet".
+pub fn blame_authors(file: &Path, lines: &[usize]) -> Option<HashMap<usize, String>> {
+    let dir = file.parent()?;
+    let mut cmd = Command::new("git");
+    cmd.args(["blame", "--line-porcelain"]).current_dir(dir);
+    for line in lines {
+        cmd.arg(format!("-L{},{}", line, line));
+    }
+    let output = cmd.arg("--").arg(file.file_name()?).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+
+    // Every blamed line starts with "<sha> <orig line> <final line>", then
+    // header fields such as "author <name>".
+    let mut authors = HashMap::new();
+    let mut current = None;
+    for row in String::from_utf8_lossy(&output.stdout).lines() {
+        let mut fields = row.split(' ');
+        let first = fields.next().unwrap_or("");
+        if first.len() == 40 && first.bytes().all(|b| b.is_ascii_hexdigit()) {
+            current = fields.nth(1).and_then(|n| n.parse::<usize>().ok());
+        } else if let (Some(name), Some(line)) = (row.strip_prefix("author "), current) {
+            authors.insert(line, name.to_string());
+        }
+    }
+    Some(authors)
+}
+
+/// A commit measured by `ssp stats`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Commit {
+    pub id: String,
+    pub short: String,
+    /// Committer date, `YYYY-MM-DD`.
+    pub date: String,
+    pub subject: String,
+}
+
+/// A file in a past tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blob {
+    /// Relative to the directory it was listed from.
+    pub path: PathBuf,
+    pub id: String,
+    pub size: u64,
+    pub symlink: bool,
+}
+
+/// Run git in `dir` and return its stdout, or its stderr as the error.
+fn run(dir: &Path, args: &[&str]) -> Result<Vec<u8>, String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| format!("cannot run git: {}", e))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(stderr.trim().trim_start_matches("fatal: ").to_string());
+    }
+    Ok(output.stdout)
+}
+
+const COMMIT_FORMAT: &str = "--format=%H%x09%h%x09%cs%x09%s";
+
+fn parse_commits(stdout: &[u8]) -> Vec<Commit> {
+    String::from_utf8_lossy(stdout)
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            Some(Commit {
+                id: fields.next()?.to_string(),
+                short: fields.next()?.to_string(),
+                date: fields.next()?.to_string(),
+                subject: fields.next().unwrap_or("").to_string(),
+            })
+        })
+        .collect()
+}
+
+/// The last `n` commits on the first-parent line of `HEAD`, oldest first.
+pub fn recent_commits(dir: &Path, n: usize) -> Result<Vec<Commit>, String> {
+    let count = format!("-n{}", n);
+    let stdout = run(dir, &["log", "--first-parent", &count, COMMIT_FORMAT, "HEAD", "--"])?;
+    let mut commits = parse_commits(&stdout);
+    commits.reverse();
+    Ok(commits)
+}
+
+/// The commit `rev` (a tag, branch or hash) points at.
+pub fn resolve_commit(dir: &Path, rev: &str) -> Result<Commit, String> {
+    let spec = format!("{}^{{commit}}", rev);
+    let unknown = || format!("unknown revision '{}'", rev);
+    let stdout = run(dir, &["log", "-n1", COMMIT_FORMAT, &spec, "--"])
+        .map_err(|e| if e.contains("bad revision") { unknown() } else { e })?;
+    parse_commits(&stdout).pop().ok_or_else(unknown)
+}
+
+/// Files below `dir` in commit `id`, with paths relative to `dir`.
+/// Submodules are left out.
+pub fn tree_blobs(dir: &Path, id: &str) -> Result<Vec<Blob>, String> {
+    let stdout = run(dir, &["ls-tree", "-r", "-l", "-z", id])?;
+    // Each entry is "<mode> <type> <id> <size>\t<path>".
+    Ok(stdout
+        .split(|&b| b == 0)
+        .filter_map(|entry| {
+            let entry = std::str::from_utf8(entry).ok()?;
+            let (meta, path) = entry.split_once('\t')?;
+            let mut fields = meta.split_whitespace();
+            let mode = fields.next()?;
+            if fields.next()? != "blob" {
+                return None;
+            }
+            let id = fields.next()?.to_string();
+            let size = fields.next()?.parse().ok()?;
+            Some(Blob {
+                path: PathBuf::from(path),
+                id,
+                size,
+                symlink: mode == "120000",
+            })
+        })
+        .collect())
+}
+
+/// Call `f` with the index and contents of each of `ids`, in order, read by
+/// a single `git cat-file --batch`.
+pub fn for_each_blob(
+    dir: &Path,
+    ids: &[&str],
+    mut f: impl FnMut(usize, &[u8]),
+) -> Result<(), String> {
+    let mut child = Command::new("git")
+        .args(["cat-file", "--batch"])
+        .current_dir(dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(|e| format!("cannot run git: {}", e))?;
+
+    // Feed the ids from another thread so a full stdout pipe can't stall us.
+    let mut stdin = child.stdin.take().ok_or("cannot write to git cat-file")?;
+    let request: String = ids.iter().map(|id| format!("{}\n", id)).collect();
+    let writer = std::thread::spawn(move || stdin.write_all(request.as_bytes()));
+
+    let mut reader = BufReader::new(child.stdout.take().ok_or("cannot read from git cat-file")?);
+    let broken = |e: std::io::Error| format!("reading git objects: {}", e);
+    let mut header = String::new();
+    let mut buf = Vec::new();
+    for i in 0..ids.len() {
+        header.clear();
+        reader.read_line(&mut header).map_err(broken)?;
+        // "<id> <type> <size>", or "<id> missing".
+        let size: usize = header
+            .split_whitespace()
+            .nth(2)
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| format!("git cat-file: {}", header.trim()))?;
+        buf.resize(size + 1, 0);
+        reader.read_exact(&mut buf).map_err(broken)?;
+        f(i, &buf[..size]);
+    }
+
+    let _ = writer.join();
+    let _ = child.wait();
+    Ok(())
+}
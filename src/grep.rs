@@ -0,0 +1,99 @@
+//! `--grep`: keep only files whose contents match a regex, with optional
+//! context lines (`--grep-context`) printed under each file by the renderer.
+
+use std::path::Path;
+
+use regex::Regex;
+
+use crate::content::{self, Content};
+use crate::tree::{Node, NodeKind};
+
+/// Fill `Node::matches` and drop every file without a match, along with
+/// directories left empty.  Binary files, and files above `max_size`, never
+/// match.
+pub fn grep_tree(node: &mut Node, re: &Regex, max_size: Option<u64>) {
+    for child in &mut node.children {
+        match child.kind {
+            NodeKind::File if max_size.is_some_and(|max| child.size > max) => {}
+            NodeKind::File => child.matches = count_matches(&child.path, re),
+            NodeKind::Dir => grep_tree(child, re, max_size),
+            NodeKind::Symlink => {}
+        }
+    }
+    node.children.retain(|c| match c.kind {
+        NodeKind::File => c.matches.is_some(),
+        NodeKind::Dir => !c.children.is_empty(),
+        NodeKind::Symlink => false,
+    });
+}
+
+/// Number of matching lines in the file, `None` if there are none or the
+/// file is unreadable or binary.
+fn count_matches(path: &Path, re: &Regex) -> Option<usize> {
+    let Content::Text { text, .. } = content::read(path).ok()? else {
+        return None;
+    };
+    let count = text.lines().filter(|l| re.is_match(l)).count();
+    (count > 0).then_some(count)
+}
+
+/// One line of `--grep-context` output.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ContextLine<'a> {
+    /// 1-based line number, text, and whether the line itself matched.
+    Line(usize, &'a str, bool),
+    /// Gap between two non-adjacent windows.
+    Separator,
+}
+
+/// Matching lines of `content` with `context` lines around each, merging
+/// overlapping windows like `grep -C`.
+pub fn context_lines<'a>(content: &'a str, re: &Regex, context: usize) -> Vec<ContextLine<'a>> {
+    let lines: Vec<&str> = content.lines().collect();
+    let hits: Vec<usize> = (0..lines.len()).filter(|&i| re.is_match(lines[i])).collect();
+
+    let mut out = Vec::new();
+    let mut next = 0; // first line not yet printed
+    for &hit in &hits {
+        let start = hit.saturating_sub(context).max(next);
+        let end = (hit + context + 1).min(lines.len());
+        if start >= end {
+            continue;
+        }
+        if next > 0 && start > next {
+            out.push(ContextLine::Separator);
+        }
+        for (i, line) in lines.iter().enumerate().take(end).skip(start) {
+            out.push(ContextLine::Line(i + 1, line, hits.binary_search(&i).is_ok()));
+        }
+        next = end;
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use regex::Regex;
+
+    use super::{ContextLine, context_lines};
+
+    #[test]
+    fn test_context_lines() {
+        let text = "a\nTODO 1\nb\nc\nd\ne\nTODO 2\nTODO 3\nf";
+        let re = Regex::new("TODO").unwrap();
+
+        let got = context_lines(text, &re, 1);
+        assert_eq!(got, vec![
+            ContextLine::Line(1, "a", false),
+            ContextLine::Line(2, "TODO 1", true),
+            ContextLine::Line(3, "b", false),
+            ContextLine::Separator,
+            ContextLine::Line(6, "e", false),
+            ContextLine::Line(7, "TODO 2", true),
+            ContextLine::Line(8, "TODO 3", true),
+            ContextLine::Line(9, "f", false),
+        ]);
+        // Matches only, still separated where they are not adjacent.
+        assert_eq!(context_lines(text, &re, 0).len(), 4);
+    }
+}
@@ -0,0 +1,260 @@
+//! Content hashing (`--hash`) and duplicate-file detection (`--duplicates`).
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sha2::Digest;
+
+use crate::cli::HashAlgo;
+use crate::tree::{Node, NodeKind, human_size};
+
+/// Bytes read for the cheap first-pass hash of duplicate candidates.
+const PARTIAL_HASH_LEN: u64 = 16 * 1024;
+
+impl HashAlgo {
+    pub fn name(self) -> &'static str {
+        match self {
+            HashAlgo::Blake3 => "blake3",
+            HashAlgo::Sha256 => "sha256",
+        }
+    }
+}
+
+/// Hash at most `limit` bytes of `path` (all of it when `None`), returning
+/// `"<algo>:<hex>"`.
+pub fn hash_file(path: &Path, algo: HashAlgo, limit: Option<u64>) -> io::Result<String> {
+    let file = File::open(path)?;
+    let mut reader: Box<dyn Read> = match limit {
+        Some(n) => Box::new(file.take(n)),
+        None => Box::new(file),
+    };
+    let mut buf = vec![0u8; 64 * 1024];
+    let hex = match algo {
+        HashAlgo::Blake3 => {
+            let mut hasher = blake3::Hasher::new();
+            loop {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+            }
+            hasher.finalize().to_hex().to_string()
+        }
+        HashAlgo::Sha256 => {
+            let mut hasher = sha2::Sha256::new();
+            loop {
+                let n = reader.read(&mut buf)?;
+                if n == 0 {
+                    break;
+                }
+                hasher.update(&buf[..n]);
+            }
+            hasher
+                .finalize()
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect()
+        }
+    };
+    Ok(format!("{}:{}", algo.name(), hex))
+}
+
+/// Fill `Node::hash` for every regular file in the tree.
+pub fn hash_tree(node: &mut Node, algo: HashAlgo) {
+    if node.kind == NodeKind::File {
+        node.hash = hash_file(&node.path, algo, None).ok();
+    }
+    for child in &mut node.children {
+        hash_tree(child, algo);
+    }
+}
+
+/// Digest without the `algo:` prefix, shortened for the tree view.
+pub fn short_digest(hash: &str) -> &str {
+    let hex = hash.split_once(':').map(|(_, h)| h).unwrap_or(hash);
+    &hex[..hex.len().min(12)]
+}
+
+// ── Duplicates ────────────────────────────────────────────────────────────────
+
+/// A set of files with identical content.
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroup {
+    pub hash: String,
+    /// Size of each copy in bytes.
+    pub size: u64,
+    /// Bytes that could be reclaimed by keeping a single copy.
+    pub wasted: u64,
+    pub paths: Vec<PathBuf>,
+}
+
+/// Group identical files.  Candidates are narrowed by size, then by a hash of
+/// their first 16 KiB, and only then fully hashed (reusing `Node::hash` when
+/// `--hash` already computed it with the same algorithm).  Groups are sorted
+/// by wasted bytes, largest first.
+pub fn find_duplicates(root: &Node, algo: HashAlgo) -> Vec<DuplicateGroup> {
+    let mut by_size: HashMap<u64, Vec<&Node>> = HashMap::new();
+    collect_files(root, &mut by_size);
+
+    let mut groups = Vec::new();
+    for (size, files) in by_size {
+        if files.len() < 2 {
+            continue;
+        }
+
+        let mut by_partial: HashMap<String, Vec<&Node>> = HashMap::new();
+        if size <= PARTIAL_HASH_LEN {
+            // The partial hash would read the whole file anyway.
+            by_partial.insert(String::new(), files);
+        } else {
+            for node in files {
+                if let Ok(h) = hash_file(&node.path, algo, Some(PARTIAL_HASH_LEN)) {
+                    by_partial.entry(h).or_default().push(node);
+                }
+            }
+        }
+
+        for candidates in by_partial.into_values().filter(|c| c.len() > 1) {
+            let mut by_full: HashMap<String, Vec<PathBuf>> = HashMap::new();
+            for node in candidates {
+                let full = match &node.hash {
+                    Some(h) if h.starts_with(algo.name()) => Some(h.clone()),
+                    _ => hash_file(&node.path, algo, None).ok(),
+                };
+                if let Some(h) = full {
+                    by_full.entry(h).or_default().push(node.path.clone());
+                }
+            }
+            for (hash, mut paths) in by_full {
+                if paths.len() > 1 {
+                    paths.sort();
+                    groups.push(DuplicateGroup {
+                        hash,
+                        size,
+                        wasted: size * (paths.len() as u64 - 1),
+                        paths,
+                    });
+                }
+            }
+        }
+    }
+
+    groups.sort_by(|a, b| b.wasted.cmp(&a.wasted).then_with(|| a.paths.cmp(&b.paths)));
+    groups
+}
+
+fn collect_files<'a>(node: &'a Node, by_size: &mut HashMap<u64, Vec<&'a Node>>) {
+    // Empty files are trivially identical; reporting them is just noise.
+    if node.kind == NodeKind::File && node.size > 0 {
+        by_size.entry(node.size).or_default().push(node);
+    }
+    for child in &node.children {
+        collect_files(child, by_size);
+    }
+}
+
+pub fn format_duplicates(groups: &[DuplicateGroup], base: &Path) -> String {
+    if groups.is_empty() {
+        return "No duplicate files found.\n".into();
+    }
+    let mut out = String::new();
+    let total: u64 = groups.iter().map(|g| g.wasted).sum();
+    for g in groups {
+        out.push_str(&format!(
+            "{} copies × {} ({} wasted)  {}\n",
+            g.paths.len(),
+            human_size(g.size),
+            human_size(g.wasted),
+            short_digest(&g.hash)
+        ));
+        for p in &g.paths {
+            out.push_str(&format!("  {}\n", p.strip_prefix(base).unwrap_or(p).display()));
+        }
+    }
+    out.push_str(&format!("\n{} groups, {} wasted\n", groups.len(), human_size(total)));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PARTIAL_HASH_LEN, find_duplicates, hash_file};
+    use crate::cli::HashAlgo;
+    use crate::walk::{WalkOptions, build_tree};
+
+    #[test]
+    fn test_hash_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("abc");
+        std::fs::write(&path, b"abc").unwrap();
+        assert_eq!(
+            hash_file(&path, HashAlgo::Sha256, None).unwrap(),
+            "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hash_file(&path, HashAlgo::Blake3, None).unwrap(),
+            "blake3:6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85"
+        );
+        assert_eq!(
+            hash_file(&path, HashAlgo::Sha256, Some(0)).unwrap(),
+            "sha256:e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+    }
+
+    #[test]
+    fn test_find_duplicates_stages() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, data: &[u8]| std::fs::write(dir.path().join(name), data).unwrap();
+        let big = vec![b'x'; PARTIAL_HASH_LEN as usize + 10];
+        let mut big_tail = big.clone();
+        *big_tail.last_mut().unwrap() = b'y';
+
+        // Same size, different content: split by the full hash.
+        write("a.txt", b"same");
+        write("b.txt", b"same");
+        write("c.txt", b"diff");
+        // Larger than the partial hash: equal first 16 KiB, different tail.
+        write("big1", &big);
+        write("big2", &big);
+        write("big3", &big_tail);
+        // Unique size, and empty files, are never candidates.
+        write("lone.txt", b"lonely");
+        write("empty1", b"");
+        write("empty2", b"");
+
+        let root = build_tree(dir.path(), &WalkOptions::default());
+        let groups = find_duplicates(&root, HashAlgo::Blake3);
+        let names: Vec<Vec<String>> = groups
+            .iter()
+            .map(|g| {
+                g.paths
+                    .iter()
+                    .map(|p| p.file_name().unwrap().to_string_lossy().into_owned())
+                    .collect()
+            })
+            .collect();
+        assert_eq!(names, [vec!["big1", "big2"], vec!["a.txt", "b.txt"]]);
+        assert_eq!(groups[0].size, big.len() as u64);
+        assert_eq!(groups[0].wasted, big.len() as u64);
+        assert_eq!(groups[1].wasted, 4);
+        assert!(groups[0].hash.starts_with("blake3:"));
+    }
+
+    #[test]
+    fn test_find_duplicates_reuses_tree_hashes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a"), b"one").unwrap();
+        std::fs::write(dir.path().join("b"), b"two").unwrap();
+        let mut root = build_tree(dir.path(), &WalkOptions::default());
+        for child in &mut root.children {
+            child.hash = Some("sha256:cafe".into());
+        }
+        // A `--hash` of the same algorithm is trusted instead of re-reading…
+        assert_eq!(find_duplicates(&root, HashAlgo::Sha256).len(), 1);
+        // …but one of another algorithm is not.
+        assert!(find_duplicates(&root, HashAlgo::Blake3).is_empty());
+    }
+}
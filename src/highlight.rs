@@ -0,0 +1,110 @@
+//! Syntax highlighting for the `--show-code` section (only used when color is on).
+
+use std::path::Path;
+
+use syntect::easy::HighlightLines;
+use syntect::highlighting::{Theme as SyntectTheme, ThemeSet};
+use syntect::parsing::SyntaxSet;
+use syntect::util::{LinesWithEndings, as_24_bit_terminal_escaped};
+
+use crate::lang;
+use crate::style::{Palette, name_style, paint_connector, paint_meta, paint_name};
+use crate::tree::{NodeKind, human_size};
+
+/// Built-in syntect theme used when the config doesn't pick one.
+pub const DEFAULT_CODE_THEME: &str = "base16-ocean.dark";
+
+pub struct CodeHighlighter {
+    syntaxes: SyntaxSet,
+    theme: SyntectTheme,
+}
+
+impl CodeHighlighter {
+    /// `theme` is the name of a bundled syntect theme (`base16-ocean.dark`,
+    /// `InspiredGitHub`, `Solarized (dark)`, …) or a path to a `.tmTheme` file.
+    pub fn new(theme: &str) -> Result<Self, String> {
+        let theme = if theme.ends_with(".tmTheme") {
+            ThemeSet::get_theme(theme).map_err(|e| format!("cannot load code theme '{}': {}", theme, e))?
+        } else {
+            let mut set = ThemeSet::load_defaults();
+            set.themes.remove(theme).ok_or_else(|| {
+                let mut names: Vec<_> = set.themes.keys().cloned().collect();
+                names.sort();
+                format!("unknown code theme '{}' (available: {})", theme, names.join(", "))
+            })?
+        };
+        Ok(Self {
+            syntaxes: SyntaxSet::load_defaults_newlines(),
+            theme,
+        })
+    }
+
+    /// One `--show-code` entry: a header with path, size and line count, then
+    /// the highlighted content with a line-number gutter.
+    pub fn render_file(
+        &self,
+        index: usize,
+        rel: &str,
+        path: &Path,
+        content: &str,
+        palette: &Palette,
+    ) -> String {
+        let line_count = content.lines().count();
+        let size = std::fs::metadata(path)
+            .map(|m| m.len())
+            .unwrap_or(content.len() as u64);
+        let style = name_style(NodeKind::File, path, lang::detect(path, Some(content)), false, palette);
+
+        let mut out = format!(
+            "{} {}. {} {}\n",
+            paint_connector("──", palette),
+            index,
+            paint_name(rel, style, &[], palette),
+            paint_meta(&format!("({}, {} lines)", human_size(size), line_count), palette),
+        );
+
+        let first_line = content.lines().next().unwrap_or("");
+        let syntax = self
+            .syntaxes
+            .find_syntax_for_file(path)
+            .ok()
+            .flatten()
+            .or_else(|| self.syntaxes.find_syntax_by_first_line(first_line))
+            .unwrap_or_else(|| self.syntaxes.find_syntax_plain_text());
+        let mut highlighter = HighlightLines::new(syntax, &self.theme);
+
+        let width = line_count.max(1).to_string().len();
+        for (i, line) in LinesWithEndings::from(content).enumerate() {
+            let text = match highlighter.highlight_line(line, &self.syntaxes) {
+                Ok(ranges) => as_24_bit_terminal_escaped(&ranges, false),
+                Err(_) => line.to_string(),
+            };
+            out.push_str(&paint_meta(&format!("{:>w$} │ ", i + 1, w = width), palette));
+            out.push_str(text.trim_end_matches(['\n', '\r']));
+            out.push_str("\x1b[0m\n");
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{CodeHighlighter, DEFAULT_CODE_THEME};
+    use crate::style::Palette;
+
+    #[test]
+    fn test_render_file() {
+        assert!(CodeHighlighter::new("no-such-theme").is_err());
+        let h = CodeHighlighter::new(DEFAULT_CODE_THEME).unwrap();
+        let code = "fn main() {\n    println!(\"hi\");\n}\n";
+        let out = h.render_file(1, "src/main.rs", Path::new("src/main.rs"), code, &Palette::default());
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].contains("src/main.rs") && lines[0].contains("3 lines"));
+        assert!(lines[1].starts_with("1 │ "));
+        // Keywords get a 24-bit foreground color.
+        assert!(lines[1].contains("\x1b[38;2;"));
+    }
+}
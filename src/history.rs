@@ -0,0 +1,349 @@
+//! `ssp stats`: tree totals and line counts at past commits, read from git
+//! objects without a checkout, shown as a trend table or sparklines.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::analyze::{self, AnalysisReport, CodeStats};
+use crate::cli::AnalyzeSort;
+use crate::content::{self, Content};
+use crate::git::{self, Commit};
+use crate::lang;
+use crate::output::Summary;
+use crate::tree::human_size;
+
+/// Languages that get their own column or sparkline, most lines first.
+const MAX_LANGUAGES: usize = 6;
+
+/// The metrics at one revision.
+pub struct Point {
+    /// The revision as given to `--at`, or the short hash.
+    pub label: String,
+    pub commit: Commit,
+    pub summary: Summary,
+    pub stats: CodeStats,
+}
+
+/// Measure `revs` in the given order or, without any, the last `history`
+/// commits, oldest first.
+pub fn collect(
+    dir: &Path,
+    revs: &[String],
+    history: usize,
+    max_size: Option<u64>,
+) -> Result<Vec<Point>, String> {
+    let commits: Vec<(String, Commit)> = if revs.is_empty() {
+        git::recent_commits(dir, history)?
+            .into_iter()
+            .map(|c| (c.short.clone(), c))
+            .collect()
+    } else {
+        revs.iter()
+            .map(|rev| Ok((rev.clone(), git::resolve_commit(dir, rev)?)))
+            .collect::<Result<_, String>>()?
+    };
+    if commits.is_empty() {
+        return Err("no commits to measure".into());
+    }
+    commits
+        .into_iter()
+        .map(|(label, commit)| measure(dir, label, commit, max_size))
+        .collect()
+}
+
+/// Measure the tree below `dir` at `commit`.  Symlinks, binary files and
+/// files above `max_size` count toward the summary but aren't analyzed.
+pub fn measure(dir: &Path, label: String, commit: Commit, max_size: Option<u64>) -> Result<Point, String> {
+    let blobs = git::tree_blobs(dir, &commit.id)?;
+    let dirs: BTreeSet<&Path> = blobs
+        .iter()
+        .flat_map(|b| b.path.ancestors().skip(1))
+        .filter(|p| !p.as_os_str().is_empty())
+        .collect();
+    let summary = Summary {
+        dirs: dirs.len(),
+        files: blobs.len(),
+        total_size: blobs.iter().map(|b| b.size).sum(),
+    };
+
+    let wanted: Vec<&git::Blob> = blobs
+        .iter()
+        .filter(|b| !b.symlink && max_size.is_none_or(|max| b.size <= max))
+        .collect();
+    let ids: Vec<&str> = wanted.iter().map(|b| b.id.as_str()).collect();
+    let mut stats = CodeStats::default();
+    git::for_each_blob(dir, &ids, |i, bytes| {
+        if let Content::Text { text, .. } = content::decode(bytes) {
+            let path = dir.join(&wanted[i].path);
+            analyze::analyze_file(&path, lang::detect(&path, Some(&text)), &text, &mut stats);
+        }
+    })?;
+
+    Ok(Point { label, commit, summary, stats })
+}
+
+/// One metric across all points.
+struct Series {
+    name: String,
+    values: Vec<u64>,
+    /// Shown with `human_size`.
+    bytes: bool,
+}
+
+impl Series {
+    fn show(&self, v: u64) -> String {
+        if self.bytes { human_size(v) } else { v.to_string() }
+    }
+
+    /// Last value minus the first, signed.
+    fn change(&self) -> String {
+        let (first, last) = (self.values[0], self.values[self.values.len() - 1]);
+        match last.cmp(&first) {
+            std::cmp::Ordering::Equal => "0".to_string(),
+            std::cmp::Ordering::Greater => format!("+{}", self.show(last - first)),
+            std::cmp::Ordering::Less => format!("-{}", self.show(first - last)),
+        }
+    }
+}
+
+/// Files, directories, size, lines and code, then lines of the
+/// `MAX_LANGUAGES` largest languages over all points.
+fn series(points: &[Point]) -> Vec<Series> {
+    let metric = |name: &str, bytes: bool, f: &dyn Fn(&Point) -> u64| Series {
+        name: name.to_string(),
+        values: points.iter().map(f).collect(),
+        bytes,
+    };
+    let mut out = vec![
+        metric("Files", false, &|p| p.summary.files as u64),
+        metric("Dirs", false, &|p| p.summary.dirs as u64),
+        metric("Size", true, &|p| p.summary.total_size),
+        metric("Lines", false, &|p| p.stats.total_lines as u64),
+        metric("Code", false, &|p| p.stats.code_lines as u64),
+    ];
+
+    let mut totals: HashMap<&str, usize> = HashMap::new();
+    for p in points {
+        for (name, s) in &p.stats.languages {
+            *totals.entry(name).or_default() += s.lines;
+        }
+    }
+    let mut languages: Vec<(&str, usize)> = totals.into_iter().collect();
+    languages.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+    for (name, _) in languages.into_iter().take(MAX_LANGUAGES) {
+        out.push(metric(name, false, &|p| {
+            p.stats.languages.get(name).map_or(0, |s| s.lines) as u64
+        }));
+    }
+    out
+}
+
+/// One row per revision, oldest first, then the change from first to last.
+pub fn format_table(points: &[Point]) -> String {
+    if points.is_empty() {
+        return String::new();
+    }
+    let series = series(points);
+    let mut header = vec!["Revision".to_string(), "Date".to_string()];
+    header.extend(series.iter().map(|s| s.name.clone()));
+    let mut rows = vec![header];
+    for (i, p) in points.iter().enumerate() {
+        let mut row = vec![p.label.clone(), p.commit.date.clone()];
+        row.extend(series.iter().map(|s| s.show(s.values[i])));
+        rows.push(row);
+    }
+    let mut change = vec!["Change".to_string(), String::new()];
+    change.extend(series.iter().map(Series::change));
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|c| {
+            rows.iter()
+                .chain([&change])
+                .map(|r| r[c].chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+    let line = |row: &[String]| {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(c, (cell, &w))| {
+                if c < 2 { format!("{:<w$}", cell) } else { format!("{:>w$}", cell) }
+            })
+            .collect();
+        format!("{}\n", cells.join("  ").trim_end())
+    };
+    let rule = format!("{}\n", "─".repeat(widths.iter().sum::<usize>() + 2 * (widths.len() - 1)));
+
+    let mut out = line(&rows[0]);
+    out.push_str(&rule);
+    for row in &rows[1..] {
+        out.push_str(&line(row));
+    }
+    if points.len() > 1 {
+        out.push_str(&rule);
+        out.push_str(&line(&change));
+    }
+    out
+}
+
+/// One sparkline per metric, with its first and last value and the change.
+pub fn format_sparklines(points: &[Point], ascii: bool) -> String {
+    if points.is_empty() {
+        return String::new();
+    }
+    let first = &points[0].label;
+    let last = &points[points.len() - 1].label;
+    let series = series(points);
+    let name_width = series.iter().map(|s| s.name.chars().count()).max().unwrap_or(0);
+    let arrow = if ascii { "->" } else { "→" };
+
+    let mut out = format!("{} {} {} ({} revisions)\n\n", first, arrow, last, points.len());
+    for s in &series {
+        out.push_str(&format!(
+            "{:<nw$}  {}  {} {} {} ({})\n",
+            s.name,
+            sparkline(&s.values, ascii),
+            s.show(s.values[0]),
+            arrow,
+            s.show(s.values[s.values.len() - 1]),
+            s.change(),
+            nw = name_width
+        ));
+    }
+    out
+}
+
+/// Values scaled between their minimum and maximum; a flat series sits
+/// in the middle.
+fn sparkline(values: &[u64], ascii: bool) -> String {
+    const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    const ASCII: [char; 8] = ['_', '.', ',', '-', '~', '=', '+', '#'];
+    let bars = if ascii { ASCII } else { BARS };
+    let min = values.iter().copied().min().unwrap_or(0);
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|&v| {
+            if max == min {
+                bars[3]
+            } else {
+                bars[((v - min) * 7 / (max - min)) as usize]
+            }
+        })
+        .collect()
+}
+
+/// `ssp stats --format json`.
+#[derive(Serialize)]
+pub struct JsonHistory {
+    pub ssp_version: String,
+    /// Absolute path of the measured directory.
+    pub root_path: String,
+    /// Oldest first.
+    pub revisions: Vec<JsonPoint>,
+}
+
+#[derive(Serialize)]
+pub struct JsonPoint {
+    pub label: String,
+    pub commit: Commit,
+    pub summary: Summary,
+    pub analysis: AnalysisReport,
+}
+
+pub fn to_json(points: Vec<Point>, dir: &Path) -> JsonHistory {
+    JsonHistory {
+        ssp_version: env!("CARGO_PKG_VERSION").to_string(),
+        root_path: dir
+            .canonicalize()
+            .unwrap_or_else(|_| dir.to_path_buf())
+            .to_string_lossy()
+            .into_owned(),
+        revisions: points
+            .into_iter()
+            .map(|p| JsonPoint {
+                analysis: AnalysisReport::new(&p.stats, AnalyzeSort::Code, None, dir),
+                label: p.label,
+                commit: p.commit,
+                summary: p.summary,
+            })
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use std::process::Command;
+
+    use super::{collect, format_sparklines, format_table, sparkline};
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .args(["-c", "user.name=Test", "-c", "user.email=test@example.com"])
+            .args(["-c", "commit.gpgsign=false", "-c", "tag.gpgsign=false"])
+            .args(args)
+            .current_dir(dir)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?}", args);
+    }
+
+    #[test]
+    fn test_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        git(root, &["init", "-q"]);
+        std::fs::write(root.join("main.rs"), "fn main() {}\n").unwrap();
+        git(root, &["add", "-A"]);
+        git(root, &["commit", "-q", "-m", "first"]);
+        std::fs::create_dir(root.join("py")).unwrap();
+        std::fs::write(root.join("py/tool.py"), "print(1)\n").unwrap();
+        git(root, &["add", "-A"]);
+        git(root, &["commit", "-q", "-m", "second"]);
+        git(root, &["tag", "v1"]);
+        std::fs::write(root.join("main.rs"), "fn main() {\n    run();\n}\n").unwrap();
+        git(root, &["commit", "-q", "-am", "third"]);
+        // Uncommitted changes are not part of any revision.
+        std::fs::write(root.join("draft.rs"), "fn draft() {}\n").unwrap();
+
+        let points = collect(root, &[], 10, None).unwrap();
+        let files: Vec<usize> = points.iter().map(|p| p.summary.files).collect();
+        assert_eq!(files, [1, 2, 2]);
+        assert_eq!(points[1].summary.dirs, 1);
+        let rust: Vec<usize> = points.iter().map(|p| p.stats.languages["Rust"].lines).collect();
+        assert_eq!(rust, [1, 1, 3]);
+        assert_eq!(points[2].commit.subject, "third");
+
+        let tagged = collect(root, &["v1".into(), "HEAD".into()], 10, None).unwrap();
+        let labels: Vec<&str> = tagged.iter().map(|p| p.label.as_str()).collect();
+        assert_eq!(labels, ["v1", "HEAD"]);
+        assert_eq!(tagged[0].commit.id, points[1].commit.id);
+        assert!(collect(root, &["nope".into()], 10, None).is_err());
+
+        let table = format_table(&tagged);
+        let lines: Vec<&str> = table.lines().collect();
+        assert!(lines[0].starts_with("Revision  Date"));
+        assert!(lines[0].ends_with("Code  Rust  Python"));
+        assert!(lines[2].starts_with("v1 "));
+        assert!(lines[5].starts_with("Change"));
+        assert!(lines[5].ends_with("+2       0"), "{}", table);
+
+        let sparks = format_sparklines(&points, true);
+        let span = format!("{} -> {} (3 revisions)\n", points[0].label, points[2].label);
+        assert!(sparks.starts_with(&span));
+        assert!(sparks.contains("\nRust    __#  1 -> 3 (+2)\n"));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!(sparkline(&[1, 5, 9], false), "▁▄█");
+        assert_eq!(sparkline(&[4, 4], false), "▄▄");
+        assert_eq!(sparkline(&[0, 7], true), "_#");
+    }
+}
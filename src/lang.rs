@@ -0,0 +1,428 @@
+//! Language detection shared by analysis, icons, coloring, code fences and
+//! the `-e` filter.
+//!
+//! In order of precedence a file's language comes from its name
+//! (`Makefile`, `Dockerfile`), an unambiguous extension, an editor modeline
+//! (`vim: ft=python`, `-*- mode: ruby -*-`), a shebang, and finally content
+//! heuristics for the extensions that mean two things (`.h`, `.m`).  Content
+//! is only looked at when the name and extension don't settle it.
+
+use std::io::Read;
+use std::path::Path;
+
+/// Bytes read from a file when its content has to be consulted.
+const HEAD_LEN: u64 = 4096;
+
+/// How a language marks line comments, for the analyzer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommentStyle {
+    /// `//` and `/* … */`
+    CStyle,
+    /// `#`
+    Hash,
+    /// `--`
+    DoubleDash,
+    /// `%`
+    Percent,
+    /// `;`
+    Semicolon,
+    None,
+}
+
+/// Broad kind of file, used for coloring and `--format pack` ordering.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    Programming,
+    Markup,
+    /// Data and configuration formats.
+    Data,
+    /// Documentation and prose.
+    Prose,
+}
+
+/// Files that are not text in any language, by extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Media {
+    Image,
+    Audio,
+    Video,
+    Archive,
+    /// Executables, libraries, fonts, databases and other binary formats.
+    Binary,
+}
+
+pub fn media_kind(ext: &str) -> Option<Media> {
+    Some(match ext {
+        "png" | "jpg" | "jpeg" | "gif" | "webp" | "ico" | "bmp" | "tiff" | "tif" | "svg"
+        | "avif" | "heif" | "heic" | "psd" => Media::Image,
+        "mp3" | "ogg" | "flac" | "wav" | "aac" | "m4a" | "opus" => Media::Audio,
+        "mp4" | "mkv" | "webm" | "avi" | "mov" | "flv" | "wmv" => Media::Video,
+        "zip" | "tar" | "gz" | "tgz" | "bz2" | "xz" | "zst" | "7z" | "rar" | "deb" | "rpm"
+        | "jar" => Media::Archive,
+        "exe" | "dll" | "so" | "dylib" | "a" | "lib" | "o" | "obj" | "class" | "wasm" | "pyc"
+        | "ttf" | "otf" | "woff" | "woff2" | "pdf" | "db" | "sqlite" | "sqlite3" | "bin" => {
+            Media::Binary
+        }
+        _ => return None,
+    })
+}
+
+#[derive(Debug)]
+pub struct Language {
+    /// Display name.
+    pub name: &'static str,
+    /// Short identifier; also the Markdown code-fence tag.
+    pub id: &'static str,
+    /// Lowercase extensions, the most typical first.
+    pub extensions: &'static [&'static str],
+    /// Lowercase file names that identify the language on their own.
+    pub filenames: &'static [&'static str],
+    /// Shebang interpreters, without version suffixes.
+    pub interpreters: &'static [&'static str],
+    pub comment: CommentStyle,
+    pub category: Category,
+}
+
+macro_rules! lang {
+    ($name:expr, $id:expr, [$($ext:expr),*], [$($file:expr),*], [$($interp:expr),*], $comment:ident, $cat:ident) => {
+        Language {
+            name: $name,
+            id: $id,
+            extensions: &[$($ext),*],
+            filenames: &[$($file),*],
+            interpreters: &[$($interp),*],
+            comment: CommentStyle::$comment,
+            category: Category::$cat,
+        }
+    };
+}
+
+pub static LANGUAGES: &[Language] = &[
+    lang!("Rust", "rust", ["rs"], [], ["rust-script"], CStyle, Programming),
+    lang!("Python", "python", ["py", "pyw", "pyi"], ["sconstruct", "sconscript"], ["python", "pypy"], Hash, Programming),
+    lang!("JavaScript", "javascript", ["js", "mjs", "cjs"], [], ["node", "nodejs"], CStyle, Programming),
+    lang!("JSX", "jsx", ["jsx"], [], [], CStyle, Programming),
+    lang!("TypeScript", "typescript", ["ts", "mts", "cts"], [], ["ts-node", "deno", "bun"], CStyle, Programming),
+    lang!("TSX", "tsx", ["tsx"], [], [], CStyle, Programming),
+    lang!("Go", "go", ["go"], [], [], CStyle, Programming),
+    lang!("C", "c", ["c", "h"], [], ["tcc"], CStyle, Programming),
+    lang!("C++", "cpp", ["cpp", "cc", "cxx", "c++", "hpp", "hxx", "hh", "h++", "ipp", "inl"], [], [], CStyle, Programming),
+    lang!("Objective-C", "objectivec", ["m", "mm"], [], [], CStyle, Programming),
+    lang!("MATLAB", "matlab", [], [], [], Percent, Programming),
+    lang!("C#", "csharp", ["cs", "csx"], [], [], CStyle, Programming),
+    lang!("Java", "java", ["java"], [], [], CStyle, Programming),
+    lang!("Kotlin", "kotlin", ["kt", "kts"], [], [], CStyle, Programming),
+    lang!("Scala", "scala", ["scala", "sc"], [], ["scala"], CStyle, Programming),
+    lang!("Groovy", "groovy", ["groovy", "gradle"], ["jenkinsfile"], ["groovy"], CStyle, Programming),
+    lang!("Swift", "swift", ["swift"], [], ["swift"], CStyle, Programming),
+    lang!("Dart", "dart", ["dart"], [], ["dart"], CStyle, Programming),
+    lang!("Zig", "zig", ["zig"], [], [], CStyle, Programming),
+    lang!("D", "d", ["d"], [], [], CStyle, Programming),
+    lang!("V", "v", ["v"], [], [], CStyle, Programming),
+    lang!("Vala", "vala", ["vala"], [], [], CStyle, Programming),
+    lang!("Ruby", "ruby", ["rb", "rake", "gemspec"], ["rakefile", "gemfile", "podfile", "vagrantfile", "brewfile"], ["ruby", "jruby"], Hash, Programming),
+    lang!("PHP", "php", ["php"], [], ["php"], CStyle, Programming),
+    lang!("Perl", "perl", ["pl", "pm"], [], ["perl"], Hash, Programming),
+    lang!("Lua", "lua", ["lua"], [], ["lua", "luajit"], DoubleDash, Programming),
+    lang!("Shell", "bash", ["sh", "bash", "zsh", "ksh"], [".bashrc", ".bash_profile", ".zshrc", ".zprofile", ".profile", "pkgbuild"], ["sh", "bash", "zsh", "ksh", "dash", "ash"], Hash, Programming),
+    lang!("Fish", "fish", ["fish"], [], ["fish"], Hash, Programming),
+    lang!("PowerShell", "powershell", ["ps1", "psm1", "psd1"], [], ["pwsh"], Hash, Programming),
+    lang!("R", "r", ["r", "rmd"], [".rprofile"], ["rscript"], Hash, Programming),
+    lang!("Elixir", "elixir", ["ex", "exs"], [], ["elixir"], Hash, Programming),
+    lang!("Nim", "nim", ["nim"], [], [], Hash, Programming),
+    lang!("Crystal", "crystal", ["cr"], [], ["crystal"], Hash, Programming),
+    lang!("Haskell", "haskell", ["hs", "lhs"], [], ["runghc", "runhaskell"], DoubleDash, Programming),
+    lang!("Ada", "ada", ["adb", "ads"], [], [], DoubleDash, Programming),
+    lang!("SQL", "sql", ["sql"], [], [], DoubleDash, Programming),
+    lang!("Clojure", "clojure", ["clj", "cljs", "cljc", "edn"], [], ["bb"], Semicolon, Programming),
+    lang!("Emacs Lisp", "elisp", ["el"], [".emacs"], [], Semicolon, Programming),
+    lang!("Scheme", "scheme", ["scm", "ss"], [], ["guile"], Semicolon, Programming),
+    lang!("Makefile", "makefile", ["mk", "mak"], ["makefile", "gnumakefile", "bsdmakefile"], ["make"], Hash, Programming),
+    lang!("Dockerfile", "dockerfile", ["dockerfile"], ["dockerfile", "containerfile"], [], Hash, Programming),
+    lang!("CMake", "cmake", ["cmake"], ["cmakelists.txt"], [], Hash, Programming),
+    lang!("HTML", "html", ["html", "htm", "xhtml"], [], [], None, Markup),
+    lang!("CSS", "css", ["css"], [], [], CStyle, Markup),
+    lang!("SCSS", "scss", ["scss", "sass"], [], [], CStyle, Markup),
+    lang!("JSON", "json", ["json", "jsonc", "json5"], [], [], None, Data),
+    lang!("TOML", "toml", ["toml"], ["cargo.lock", "pipfile"], [], Hash, Data),
+    lang!("YAML", "yaml", ["yaml", "yml"], [], [], Hash, Data),
+    lang!("XML", "xml", ["xml", "xsd", "xsl", "xslt", "plist"], [], [], None, Data),
+    lang!("INI", "ini", ["ini", "cfg", "conf"], [".editorconfig", ".gitconfig"], [], Hash, Data),
+    lang!("Markdown", "markdown", ["md", "markdown", "mdx"], [], [], None, Prose),
+    lang!("reStructuredText", "rst", ["rst"], [], [], None, Prose),
+    lang!("TeX", "latex", ["tex", "sty", "ltx"], [], [], Percent, Prose),
+];
+
+/// Look a language up by identifier, display name or extension, ignoring
+/// case (`rust`, `C++`, `py`).  Also accepts the usual modeline spellings
+/// (`sh`, `c++`, `objc`).
+pub fn by_name(name: &str) -> Option<&'static Language> {
+    let name = name.to_lowercase();
+    let name = match name.as_str() {
+        "objc" | "objective-c" => "objectivec",
+        "js" => "javascript",
+        "shell" | "zsh" | "sh" => "bash",
+        "make" => "makefile",
+        "tex" => "latex",
+        "emacs-lisp" | "lisp-interaction" => "elisp",
+        other => other,
+    };
+    LANGUAGES
+        .iter()
+        .find(|l| l.id == name || l.name.to_lowercase() == name)
+        .or_else(|| LANGUAGES.iter().find(|l| l.extensions.contains(&name)))
+}
+
+/// Detect the language of `path`, given its content if the caller already
+/// has it.  Without content, only the name and extension are used and
+/// nothing is read from disk.
+pub fn detect(path: &Path, content: Option<&str>) -> Option<&'static Language> {
+    detect_with(
+        path,
+        || content.map(|c| c.to_string()),
+        || content.is_some() && has_cpp_siblings(path),
+    )
+}
+
+/// Detect the language of `path`, reading the first few KiB of the file only
+/// if the name and extension are not enough.
+pub fn detect_path(path: &Path) -> Option<&'static Language> {
+    detect_with(path, || read_head(path), || has_cpp_siblings(path))
+}
+
+/// Detection for the walker, which already knows whether the directory
+/// holds C++ sources (see `is_cpp_source`) and so needn't list it again.
+/// Reads the head of the file only with `sniff`, and only if the name and
+/// extension are not enough; otherwise nothing is read from disk.
+pub fn detect_in_dir(path: &Path, cpp_siblings: bool, sniff: bool) -> Option<&'static Language> {
+    detect_with(path, || if sniff { read_head(path) } else { None }, || cpp_siblings)
+}
+
+fn detect_with(
+    path: &Path,
+    head: impl FnOnce() -> Option<String>,
+    cpp_siblings: impl FnOnce() -> bool,
+) -> Option<&'static Language> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    if let Some(lang) = LANGUAGES.iter().find(|l| l.filenames.contains(&name.as_str())) {
+        return Some(lang);
+    }
+    // `Dockerfile.dev`, `prod.Dockerfile`
+    if name.starts_with("dockerfile.") {
+        return by_name("dockerfile");
+    }
+
+    let ext = Path::new(&name).extension().and_then(|e| e.to_str()).unwrap_or("");
+    if !ext.is_empty() && !matches!(ext, "h" | "m") {
+        if let Some(lang) = LANGUAGES.iter().find(|l| l.extensions.contains(&ext)) {
+            return Some(lang);
+        }
+        if media_kind(ext).is_some() {
+            return None;
+        }
+    }
+
+    let head = head();
+    if let Some(head) = &head {
+        if let Some(lang) = modeline(head).and_then(by_name) {
+            return Some(lang);
+        }
+        if let Some(lang) = shebang(head) {
+            return Some(lang);
+        }
+    }
+    let head = head.as_deref().unwrap_or("");
+    match ext {
+        "h" if looks_like_cpp(head) || cpp_siblings() => by_name("cpp"),
+        "h" => by_name("c"),
+        "m" if looks_like_matlab(head) => by_name("matlab"),
+        "m" => by_name("objectivec"),
+        _ => None,
+    }
+}
+
+fn read_head(path: &Path) -> Option<String> {
+    let mut buf = Vec::new();
+    std::fs::File::open(path)
+        .and_then(|f| f.take(HEAD_LEN).read_to_end(&mut buf))
+        .ok()?;
+    Some(String::from_utf8_lossy(&buf).into_owned())
+}
+
+/// The file type named by a vim (`vim: set ft=python:`) or emacs
+/// (`-*- mode: ruby -*-`) modeline in the first or last few lines.
+fn modeline(head: &str) -> Option<&str> {
+    let lines: Vec<&str> = head.lines().collect();
+    let edges = lines.iter().take(5).chain(lines.iter().rev().take(5));
+    for line in edges {
+        if let Some(start) = line.find("-*-") {
+            let rest = &line[start + 3..];
+            if let Some(end) = rest.find("-*-") {
+                let inner = rest[..end].trim();
+                let mode = match inner.to_lowercase().find("mode:") {
+                    Some(i) => inner[i + 5..].split(';').next().unwrap_or(""),
+                    None if !inner.contains(':') => inner,
+                    None => "",
+                };
+                let mode = mode.trim();
+                if !mode.is_empty() {
+                    return Some(mode);
+                }
+            }
+        }
+        for marker in ["vim:", "vi:", "ex:"] {
+            let Some(start) = line.find(marker) else {
+                continue;
+            };
+            for key in ["filetype=", "ft=", "syntax="] {
+                if let Some(i) = line[start..].find(key) {
+                    let value = &line[start + i + key.len()..];
+                    let end = value
+                        .find(|c: char| !(c.is_alphanumeric() || c == '+' || c == '-' || c == '_'))
+                        .unwrap_or(value.len());
+                    if end > 0 {
+                        return Some(&value[..end]);
+                    }
+                }
+            }
+        }
+    }
+    None
+}
+
+/// The language of a `#!` interpreter line: `#!/bin/bash`,
+/// `#!/usr/bin/env python3`, `#!/usr/bin/env -S node --flag`.
+fn shebang(head: &str) -> Option<&'static Language> {
+    let line = head.lines().next()?.strip_prefix("#!")?;
+    let mut words = line.split_whitespace();
+    let mut interp = words.next()?.rsplit('/').next()?;
+    if interp == "env" {
+        interp = words.find(|w| !w.starts_with('-'))?;
+    }
+    // python3.12 → python, ruby2.7 → ruby
+    let interp = interp.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+    LANGUAGES.iter().find(|l| l.interpreters.contains(&interp))
+}
+
+fn looks_like_cpp(head: &str) -> bool {
+    const MARKERS: &[&str] = &[
+        "namespace ", "class ", "template <", "template<", "std::", "public:",
+        "private:", "#include <iostream>", "#include <string>", "#include <vector>",
+        "constexpr ", "nullptr",
+    ];
+    MARKERS.iter().any(|m| head.contains(m))
+}
+
+/// A `.h` next to C++ sources is a C++ header.
+fn has_cpp_siblings(path: &Path) -> bool {
+    let Some(dir) = path.parent() else {
+        return false;
+    };
+    let Ok(entries) = std::fs::read_dir(if dir.as_os_str().is_empty() { Path::new(".") } else { dir }) else {
+        return false;
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .any(|e| is_cpp_source(&e.file_name().to_string_lossy()))
+}
+
+/// A C++ source or header by name, which makes `.h` files next to it C++.
+pub fn is_cpp_source(name: &str) -> bool {
+    let name = name.to_lowercase();
+    [".cpp", ".cc", ".cxx", ".hpp", ".hh", ".hxx"].iter().any(|s| name.ends_with(s))
+}
+
+/// MATLAB/Octave rather than Objective-C: `%` comments, `function`
+/// definitions, and none of Objective-C's `#import`/`@interface`.
+fn looks_like_matlab(head: &str) -> bool {
+    let objc = ["#import", "#include", "@interface", "@implementation", "@end", "@property"]
+        .iter()
+        .any(|m| head.contains(m));
+    let matlab = head.lines().map(str::trim_start).any(|l| {
+        l.starts_with('%') || l.starts_with("function ") || l.trim_end() == "end"
+    });
+    matlab && !objc
+}
+
+/// `-e`/`--extension`: the file has extension `filter`, or is detected as
+/// the language `filter` names (`-e py` also matches a `#!/usr/bin/env
+/// python3` script; `-e cpp` a C++ `.h` header; `-e rust` any `.rs` file).
+/// `lang` is the file's language as detected by the walker.
+pub fn matches_filter(path: &Path, lang: Option<&'static Language>, filter: &str) -> bool {
+    let ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
+    if ext.eq_ignore_ascii_case(filter) {
+        return true;
+    }
+    match (by_name(filter), lang) {
+        (Some(wanted), Some(found)) => std::ptr::eq(wanted, found),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{by_name, detect, detect_in_dir, matches_filter};
+
+    #[test]
+    fn test_detect() {
+        let cases: &[(&str, Option<&str>, Option<&str>)] = &[
+            // Extensions
+            ("src/main.rs", None, Some("rust")),
+            ("app/View.TSX", None, Some("tsx")),
+            ("lib/util.py", Some("#!/usr/bin/env ruby\n"), Some("python")),
+            // File names
+            ("Makefile", None, Some("makefile")),
+            ("GNUmakefile", None, Some("makefile")),
+            ("Dockerfile", None, Some("dockerfile")),
+            ("docker/Dockerfile.dev", None, Some("dockerfile")),
+            ("CMakeLists.txt", None, Some("cmake")),
+            ("Rakefile", None, Some("ruby")),
+            ("Jenkinsfile", None, Some("groovy")),
+            // Shebangs
+            ("bin/deploy", Some("#!/usr/bin/env python3\nimport sys\n"), Some("python")),
+            ("bin/run", Some("#!/bin/bash\nset -e\n"), Some("bash")),
+            ("bin/serve", Some("#!/usr/bin/env -S node --no-warnings\n"), Some("javascript")),
+            ("bin/tool", Some("#!/usr/local/bin/ruby2.7 -w\n"), Some("ruby")),
+            ("bin/unknown", Some("#!/usr/bin/env frobnicate\n"), None),
+            ("bin/plain", Some("just text\n"), None),
+            // Modelines, which win over a shebang
+            ("scripts/build", Some("#!/bin/sh\n# vim: set ft=python:\n"), Some("python")),
+            ("notes.txt", Some("# -*- mode: ruby -*-\nputs 1\n"), Some("ruby")),
+            ("conf/site", Some("x\n\n/* vim: syntax=c++ */\n"), Some("cpp")),
+            ("misc/thing", Some("# -*- coding: utf-8 -*-\n"), None),
+            // Ambiguous extensions
+            ("include/point.h", Some("typedef struct { int x, y; } point;\n"), Some("c")),
+            ("include/point.h", Some("namespace geo {\nclass Point;\n}\n"), Some("cpp")),
+            ("include/point.h", None, Some("c")),
+            ("src/AppDelegate.m", Some("#import <UIKit/UIKit.h>\n@implementation AppDelegate\n@end\n"), Some("objectivec")),
+            ("analysis/fit.m", Some("% Fit a line\nfunction y = fit(x)\n  y = 2 * x;\nend\n"), Some("matlab")),
+            ("analysis/fit.m", None, Some("objectivec")),
+            // Not a language
+            ("photo.png", Some("# vim: ft=python\n"), None),
+            ("LICENSE", Some("MIT License\n"), None),
+        ];
+        for &(path, content, expected) in cases {
+            let got = detect(Path::new(path), content).map(|l| l.id);
+            assert_eq!(got, expected, "{} {:?}", path, content);
+        }
+
+        assert_eq!(by_name("C++").map(|l| l.id), Some("cpp"));
+        assert_eq!(by_name("py").map(|l| l.id), Some("python"));
+        assert_eq!(by_name("sh").map(|l| l.id), Some("bash"));
+        assert!(by_name("nope").is_none());
+
+        // The walker knows about C++ siblings without listing the directory.
+        assert_eq!(detect_in_dir(Path::new("include/point.h"), true, false).map(|l| l.id), Some("cpp"));
+
+        let matches = |path: &str, filter| {
+            let path = Path::new(path);
+            matches_filter(path, detect(path, None), filter)
+        };
+        assert!(matches("a/lib.rs", "rs"));
+        assert!(matches("a/lib.rs", "rust"));
+        assert!(matches("a/x.PY", "py"));
+        assert!(!matches("a/lib.rs", "py"));
+    }
+}
@@ -0,0 +1,428 @@
+//! Line classification for `--analyze`: a small per-language state machine
+//! that tracks block comments (nested where the language allows it), string
+//! literals and doc comments, so a `/*` inside a string or a `*` at the start
+//! of a continued expression don't count as comments.
+//!
+//! A line with any code on it is code, even if it also holds a comment.  A
+//! line inside a block comment or docstring counts as comment (or doc) even
+//! when empty, so a doc block is counted in one piece.
+
+use crate::lang::{CommentStyle, Language};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineKind {
+    Blank,
+    Code,
+    Comment,
+    Doc,
+}
+
+/// A string literal: opening and closing delimiter, whether `\` escapes the
+/// next character, and whether it may span lines.
+#[derive(Debug, Clone, Copy)]
+struct Quote {
+    open: &'static str,
+    close: &'static str,
+    escapes: bool,
+    multiline: bool,
+}
+
+const fn quote(open: &'static str, close: &'static str, escapes: bool, multiline: bool) -> Quote {
+    Quote { open, close, escapes, multiline }
+}
+
+const DQ: Quote = quote("\"", "\"", true, false);
+const SQ: Quote = quote("'", "'", true, false);
+/// Backtick template (JS) or raw (Go) strings.
+const TEMPLATE: Quote = quote("`", "`", true, true);
+const GO_RAW: Quote = quote("`", "`", false, true);
+const RUST_STR: Quote = quote("\"", "\"", true, true);
+const RUST_RAW: Quote = quote("r#\"", "\"#", false, true);
+const TRIPLE_DQ: Quote = quote("\"\"\"", "\"\"\"", true, true);
+const TRIPLE_SQ: Quote = quote("'''", "'''", true, true);
+const SHELL_SQ: Quote = quote("'", "'", false, false);
+const LUA_LONG: Quote = quote("[[", "]]", false, true);
+
+/// Comment and string syntax of a language.
+#[derive(Debug, Clone, Copy)]
+pub struct Syntax {
+    line: &'static [&'static str],
+    block: &'static [(&'static str, &'static str)],
+    nested: bool,
+    /// Line-comment prefixes that make a doc comment (`///`, `//!`).
+    doc_line: &'static [&'static str],
+    /// Block-comment openers that make a doc comment (`/**`, `/*!`).
+    doc_block: &'static [&'static str],
+    quotes: &'static [Quote],
+    /// Rust: `'x'` is a char literal, but `'a` alone is a lifetime.
+    char_literals: bool,
+    /// Python: a triple-quoted string opening a line is a docstring.
+    docstrings: bool,
+}
+
+const PLAIN: Syntax = Syntax {
+    line: &[],
+    block: &[],
+    nested: false,
+    doc_line: &[],
+    doc_block: &[],
+    quotes: &[],
+    char_literals: false,
+    docstrings: false,
+};
+
+const C_BLOCK: &[(&str, &str)] = &[("/*", "*/")];
+const C_LIKE: Syntax = Syntax {
+    line: &["//"],
+    block: C_BLOCK,
+    doc_line: &["///"],
+    doc_block: &["/**"],
+    quotes: &[DQ, SQ],
+    ..PLAIN
+};
+const HASH: Syntax = Syntax {
+    line: &["#"],
+    quotes: &[DQ, SQ],
+    ..PLAIN
+};
+
+impl Syntax {
+    /// The syntax for a detected language; files of unknown language have
+    /// no comments, so every non-blank line is code.
+    pub fn for_language(lang: Option<&Language>) -> Syntax {
+        let Some(lang) = lang else {
+            return PLAIN;
+        };
+        match lang.id {
+            "rust" => Syntax {
+                nested: true,
+                doc_line: &["///", "//!"],
+                doc_block: &["/**", "/*!"],
+                quotes: &[RUST_RAW, RUST_STR],
+                char_literals: true,
+                ..C_LIKE
+            },
+            "swift" | "kotlin" | "scala" => Syntax { nested: true, ..C_LIKE },
+            "javascript" | "typescript" | "jsx" | "tsx" => Syntax {
+                quotes: &[DQ, SQ, TEMPLATE],
+                ..C_LIKE
+            },
+            "go" => Syntax {
+                quotes: &[DQ, SQ, GO_RAW],
+                ..C_LIKE
+            },
+            "d" => Syntax {
+                block: &[("/*", "*/"), ("/+", "+/")],
+                nested: true,
+                ..C_LIKE
+            },
+            "css" => Syntax { line: &[], ..C_LIKE },
+            "php" => Syntax { line: &["//", "#"], ..C_LIKE },
+            "python" => Syntax {
+                quotes: &[TRIPLE_DQ, TRIPLE_SQ, DQ, SQ],
+                docstrings: true,
+                ..HASH
+            },
+            "nim" => Syntax {
+                block: &[("#[", "]#")],
+                nested: true,
+                doc_line: &["##"],
+                quotes: &[TRIPLE_DQ, DQ],
+                ..HASH
+            },
+            "powershell" => Syntax { block: &[("<#", "#>")], ..HASH },
+            "bash" | "fish" => Syntax { quotes: &[DQ, SHELL_SQ], ..HASH },
+            "lua" => Syntax {
+                line: &["--"],
+                block: &[("--[[", "]]")],
+                doc_line: &["---"],
+                quotes: &[DQ, SQ, LUA_LONG],
+                ..PLAIN
+            },
+            "haskell" => Syntax {
+                line: &["--"],
+                block: &[("{-", "-}")],
+                nested: true,
+                doc_line: &["-- |", "-- ^"],
+                doc_block: &["{- |"],
+                quotes: &[DQ],
+                ..PLAIN
+            },
+            "sql" => Syntax {
+                line: &["--"],
+                block: C_BLOCK,
+                quotes: &[SQ, DQ],
+                ..PLAIN
+            },
+            "matlab" => Syntax {
+                line: &["%"],
+                block: &[("%{", "%}")],
+                quotes: &[DQ],
+                ..PLAIN
+            },
+            "html" | "xml" | "markdown" => Syntax {
+                block: &[("<!--", "-->")],
+                ..PLAIN
+            },
+            "scheme" => Syntax {
+                line: &[";"],
+                block: &[("#|", "|#")],
+                nested: true,
+                quotes: &[DQ],
+                ..PLAIN
+            },
+            _ => match lang.comment {
+                CommentStyle::CStyle => C_LIKE,
+                CommentStyle::Hash => HASH,
+                CommentStyle::DoubleDash => Syntax { line: &["--"], quotes: &[DQ], ..PLAIN },
+                CommentStyle::Percent => Syntax { line: &["%"], ..PLAIN },
+                CommentStyle::Semicolon => Syntax { line: &[";"], quotes: &[DQ], ..PLAIN },
+                CommentStyle::None => PLAIN,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum State {
+    Code,
+    Block { open: &'static str, close: &'static str, depth: usize, doc: bool },
+    Str { quote: Quote, doc: bool },
+}
+
+/// The result of `scan`.
+pub struct Scan {
+    /// One kind per line of the content (as split by `str::lines`).
+    pub kinds: Vec<LineKind>,
+    /// The content with comments and the insides of string literals blanked
+    /// out, for scanning code for keywords and braces.
+    pub code: String,
+    /// The content with everything but comment text (and docstrings)
+    /// blanked out, comment markers included.
+    pub comments: String,
+}
+
+/// Classify each line of `content`, and split it into code and comments.
+pub fn scan(content: &str, syntax: &Syntax) -> Scan {
+    let mut state = State::Code;
+    let mut kinds = Vec::new();
+    let mut masked = String::with_capacity(content.len());
+    let mut comments = String::with_capacity(content.len());
+    let blank = |out: &mut String, s: &str| out.extend(s.chars().map(|_| ' '));
+
+    for line in content.lines() {
+        let (mut code, mut comment, mut doc) = (false, false, false);
+        match state {
+            State::Block { doc: true, .. } | State::Str { doc: true, .. } => doc = true,
+            State::Block { .. } => comment = true,
+            State::Str { .. } => code = true,
+            State::Code => {}
+        }
+        if matches!(state, State::Code) && line.trim().is_empty() {
+            kinds.push(LineKind::Blank);
+            masked.push('\n');
+            comments.push('\n');
+            continue;
+        }
+
+        let mut i = 0;
+        while i < line.len() {
+            let rest = &line[i..];
+            match state {
+                State::Code => {
+                    let c = rest.chars().next().unwrap();
+                    if c.is_whitespace() {
+                        masked.push(c);
+                        comments.push(c);
+                        i += c.len_utf8();
+                        continue;
+                    }
+                    if let Some(&(open, close)) = syntax.block.iter().find(|(o, _)| rest.starts_with(o)) {
+                        let is_doc = is_doc_marker(rest, syntax.doc_block, close);
+                        state = State::Block { open, close, depth: 1, doc: is_doc };
+                        if is_doc { doc = true } else { comment = true }
+                        blank(&mut masked, open);
+                        blank(&mut comments, open);
+                        i += open.len();
+                        continue;
+                    }
+                    if let Some(marker) = syntax.line.iter().find(|m| rest.starts_with(*m)) {
+                        let is_doc = is_doc_marker(rest, syntax.doc_line, "");
+                        if is_doc { doc = true } else { comment = true }
+                        let marker = syntax
+                            .doc_line
+                            .iter()
+                            .filter(|m| is_doc && rest.starts_with(*m))
+                            .max_by_key(|m| m.len())
+                            .unwrap_or(marker);
+                        blank(&mut comments, marker);
+                        comments.push_str(&rest[marker.len()..]);
+                        break;
+                    }
+                    if let Some(&q) = syntax.quotes.iter().find(|q| rest.starts_with(q.open)) {
+                        let docstring = syntax.docstrings
+                            && q.multiline
+                            && !code
+                            && line[..i].trim().is_empty();
+                        state = State::Str { quote: q, doc: docstring };
+                        if docstring { doc = true } else { code = true }
+                        masked.push_str(q.open);
+                        blank(&mut comments, q.open);
+                        i += q.open.len();
+                        continue;
+                    }
+                    if syntax.char_literals && c == '\'' {
+                        let len = char_literal_len(rest);
+                        blank(&mut masked, &rest[..len]);
+                        blank(&mut comments, &rest[..len]);
+                        i += len;
+                        code = true;
+                        continue;
+                    }
+                    code = true;
+                    masked.push(c);
+                    comments.push(' ');
+                    i += c.len_utf8();
+                }
+                State::Block { open, close, depth, doc: is_doc } => {
+                    let len = if syntax.nested && rest.starts_with(open) {
+                        state = State::Block { open, close, depth: depth + 1, doc: is_doc };
+                        blank(&mut comments, open);
+                        open.len()
+                    } else if rest.starts_with(close) {
+                        state = if depth == 1 {
+                            State::Code
+                        } else {
+                            State::Block { open, close, depth: depth - 1, doc: is_doc }
+                        };
+                        blank(&mut comments, close);
+                        close.len()
+                    } else {
+                        let c = rest.chars().next().unwrap();
+                        comments.push(c);
+                        c.len_utf8()
+                    };
+                    blank(&mut masked, &rest[..len]);
+                    i += len;
+                }
+                State::Str { quote: q, doc: is_doc } => {
+                    let len = if q.escapes && rest.starts_with('\\') {
+                        1 + rest[1..].chars().next().map_or(0, char::len_utf8)
+                    } else if rest.starts_with(q.close) {
+                        state = State::Code;
+                        masked.push_str(q.close);
+                        blank(&mut comments, q.close);
+                        i += q.close.len();
+                        continue;
+                    } else {
+                        rest.chars().next().unwrap().len_utf8()
+                    };
+                    blank(&mut masked, &rest[..len]);
+                    if is_doc {
+                        comments.push_str(&rest[..len]);
+                    } else {
+                        blank(&mut comments, &rest[..len]);
+                    }
+                    i += len;
+                }
+            }
+        }
+        if let State::Str { quote: q, .. } = state
+            && !q.multiline
+        {
+            // Unterminated single-line string: don't let it swallow the file.
+            state = State::Code;
+        }
+        masked.push('\n');
+        comments.push('\n');
+
+        kinds.push(if code {
+            LineKind::Code
+        } else if doc {
+            LineKind::Doc
+        } else if comment {
+            LineKind::Comment
+        } else {
+            LineKind::Blank
+        });
+    }
+    Scan { kinds, code: masked, comments }
+}
+
+/// Whether `rest` opens a doc comment with one of `markers`.  A marker
+/// followed by its own last character (`////`, `/***`) or by the end of the
+/// comment (`/**/`) is an ordinary comment.
+fn is_doc_marker(rest: &str, markers: &[&str], close: &str) -> bool {
+    markers.iter().any(|m| {
+        let Some(after) = rest.strip_prefix(m) else {
+            return false;
+        };
+        let last = m.chars().last().unwrap();
+        !after.starts_with(last) && (close.is_empty() || !close.starts_with(last) || !after.starts_with(&close[1..]))
+    })
+}
+
+/// Length of a char literal (`'x'`, `'\n'`, `'\u{1F600}'`) at the start of
+/// `s`, or 1 for a lone `'` (a Rust lifetime or label).
+fn char_literal_len(s: &str) -> usize {
+    let body = &s[1..];
+    if let Some(escaped) = body.strip_prefix('\\') {
+        return match escaped.find('\'') {
+            Some(end) => 2 + end + 1,
+            None => 1,
+        };
+    }
+    let mut chars = body.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), Some('\'')) => 1 + c.len_utf8() + 1,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{LineKind, Syntax, scan};
+    use crate::lang;
+
+    /// Golden files annotate every line with its expected kind:
+    /// `B|` blank, `C|` code, `M|` comment, `D|` doc.
+    const GOLDEN: &[(&str, &str)] = &[
+        ("main.rs", include_str!("../testdata/lexer/rust.golden")),
+        ("app.py", include_str!("../testdata/lexer/python.golden")),
+        ("util.c", include_str!("../testdata/lexer/c.golden")),
+        ("index.ts", include_str!("../testdata/lexer/typescript.golden")),
+        ("page.html", include_str!("../testdata/lexer/html.golden")),
+        ("init.lua", include_str!("../testdata/lexer/lua.golden")),
+        ("Main.hs", include_str!("../testdata/lexer/haskell.golden")),
+        ("build.sh", include_str!("../testdata/lexer/shell.golden")),
+        ("schema.sql", include_str!("../testdata/lexer/sql.golden")),
+    ];
+
+    #[test]
+    fn test_golden() {
+        for (name, golden) in GOLDEN {
+            let mut expected = Vec::new();
+            let mut source = String::new();
+            for line in golden.lines() {
+                let (tag, text) = line.split_once('|').expect("golden line needs a `K|` prefix");
+                expected.push(match tag {
+                    "B" => LineKind::Blank,
+                    "C" => LineKind::Code,
+                    "M" => LineKind::Comment,
+                    "D" => LineKind::Doc,
+                    _ => panic!("{}: bad tag {:?}", name, tag),
+                });
+                source.push_str(text);
+                source.push('\n');
+            }
+            let syntax = Syntax::for_language(lang::detect(Path::new(name), Some(&source)));
+            let got = scan(&source, &syntax).kinds;
+            for (i, (g, e)) in got.iter().zip(&expected).enumerate() {
+                assert_eq!(g, e, "{}:{}: {:?}", name, i + 1, source.lines().nth(i).unwrap());
+            }
+            assert_eq!(got.len(), expected.len(), "{}", name);
+        }
+    }
+}
@@ -1,23 +1,42 @@
 mod analyze;
 mod cli;
+mod complexity;
 mod config;
+mod content;
+mod diagram;
+mod diff;
 mod git;
+mod grep;
+mod hash;
+mod highlight;
+mod history;
+mod html;
 mod icons;
+mod lang;
+mod lexer;
 mod output;
+mod pack;
+mod redact;
 mod render;
+mod search;
 mod style;
+mod todos;
 mod tree;
+#[cfg(feature = "tree-sitter")]
+mod treesitter;
+mod tui;
 mod walk;
+mod watch;
 
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::io::Write as _;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use clap::Parser;
 
-use cli::{Args, ColorWhen, IconWhen, OutputFormat, SortKey};
+use cli::{AnalyzeFormat, AnalyzeMode, Args, ColorWhen, Command, DiffArgs, HashAlgo, IconWhen, MarkdownStyle, OutputFormat, SortKey, StatsArgs};
 use config::{ConfigFile, Theme};
-use output::{Summary, to_flat_list, to_json, to_markdown};
+use output::{JsonOptions, MarkdownOptions, Summary, to_flat_list, to_json, to_markdown};
 use render::{RenderContext, RenderOptions, render_tree};
 use style::{Palette, StyleConfig};
 use walk::{WalkOptions, build_tree};
@@ -60,6 +79,12 @@ fn main() {
         return;
     }
 
+    // ── --json-schema ────────────────────────────────────────────────────────
+    if args.json_schema {
+        print!("{}", output::JSON_SCHEMA);
+        return;
+    }
+
     // ── Load config file ─────────────────────────────────────────────────────
     let config_file: Option<ConfigFile> = if args.no_config {
         None
@@ -69,8 +94,7 @@ fn main() {
 
     let cfg_defaults = config_file
         .as_ref()
-        .map(|c| &c.defaults)
-        .map(|d| d.clone())
+        .map(|c| c.defaults.clone())
         .unwrap_or_default();
 
     // ── Resolve active theme ──────────────────────────────────────────────────
@@ -106,20 +130,50 @@ fn main() {
     let style_cfg = StyleConfig::resolve(color_when, icon_when, args.no_icons, args.ascii);
     let palette = Palette::from_theme(&theme, style_cfg.use_color);
 
+    // ── ssp stats ─────────────────────────────────────────────────────────────
+    if let Some(Command::Stats(stats_args)) = &args.command {
+        let final_output = run_stats(stats_args, &args, &style_cfg);
+        write_output(&final_output, args.output_file.as_deref());
+        return;
+    }
+
+    // ── Snapshot import (--from-json) ─────────────────────────────────────────
+    let snapshot = args.from_json.as_deref().map(|file| {
+        let text = match std::fs::read_to_string(file) {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("ssp: cannot read '{}': {}", file, e);
+                std::process::exit(1);
+            }
+        };
+        match output::from_json(&text) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("ssp: invalid snapshot '{}': {}", file, e);
+                std::process::exit(1);
+            }
+        }
+    });
+
     // ── Target directory ─────────────────────────────────────────────────────
-    let target_dir: PathBuf = args
-        .directory
-        .as_deref()
-        .map(PathBuf::from)
-        .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
+    let target_dir: PathBuf = match &snapshot {
+        Some((_, base)) => base.clone(),
+        None => args
+            .directory
+            .as_deref()
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."))),
+    };
 
-    if !target_dir.exists() {
-        eprintln!("ssp: '{}' does not exist", target_dir.display());
-        std::process::exit(1);
-    }
-    if !target_dir.is_dir() {
-        eprintln!("ssp: '{}' is not a directory", target_dir.display());
-        std::process::exit(1);
+    if snapshot.is_none() {
+        if !target_dir.exists() {
+            eprintln!("ssp: '{}' does not exist", target_dir.display());
+            std::process::exit(1);
+        }
+        if !target_dir.is_dir() {
+            eprintln!("ssp: '{}' is not a directory", target_dir.display());
+            std::process::exit(1);
+        }
     }
 
     // ── Ignore set ────────────────────────────────────────────────────────────
@@ -140,26 +194,77 @@ fn main() {
     }
     let dirs_only = args.dirs_only;
     // Back-compat -sc
-    let show_code = args.show_code || args.sc_compat;
+    let mut show_code = args.show_code || args.sc_compat;
+    let mut analyze = args.analyze.is_some();
+    let mut todos = args.todos;
+    // A snapshot carries no file contents.
+    if snapshot.is_some() && (show_code || analyze || todos) {
+        eprintln!("ssp: --show-code, --analyze and --todos are ignored with --from-json");
+        show_code = false;
+        analyze = false;
+        todos = false;
+    }
 
     // ── Sort key ─────────────────────────────────────────────────────────────
-    let sort_key = args.sort.unwrap_or_else(|| {
-        match cfg_defaults.sort.as_str() {
-            "size" => SortKey::Size,
-            "time" => SortKey::Time,
-            "ext"  => SortKey::Ext,
-            "none" => SortKey::None,
-            _      => SortKey::Name,
-        }
+    let sort_key = args.sort.unwrap_or(match cfg_defaults.sort.as_str() {
+        "size" => SortKey::Size,
+        "time" => SortKey::Time,
+        "ext"  => SortKey::Ext,
+        "lines" => SortKey::Lines,
+        "none" => SortKey::None,
+        _      => SortKey::Name,
     });
 
     // ── Git status ────────────────────────────────────────────────────────────
-    let git_status = if args.git {
+    let git_status = if args.git && snapshot.is_none() {
         git::collect_status(&target_dir)
     } else {
         None
     };
 
+    // ── --find ────────────────────────────────────────────────────────────────
+    let finder = args.find.as_deref().map(|query| {
+        search::Matcher::new(query, args.regex).unwrap_or_else(|e| {
+            eprintln!("ssp: {}", e);
+            std::process::exit(1);
+        })
+    });
+
+    // ── --grep ────────────────────────────────────────────────────────────────
+    let grep_re = match args.grep.as_deref() {
+        Some(_) if snapshot.is_some() => {
+            eprintln!("ssp: --grep is ignored with --from-json");
+            None
+        }
+        Some(pattern) => Some(regex::Regex::new(pattern).unwrap_or_else(|e| {
+            eprintln!("ssp: invalid --grep pattern '{}': {}", pattern, e);
+            std::process::exit(1);
+        })),
+        None => None,
+    };
+
+    // ── Redaction ─────────────────────────────────────────────────────────────
+    let redactor = if args.no_redact {
+        None
+    } else {
+        let cfg = config_file.as_ref().map(|c| c.redact.clone()).unwrap_or_default();
+        Some(redact::Redactor::new(&cfg.patterns, &cfg.allowlist).unwrap_or_else(|e| {
+            eprintln!("ssp: {}", e);
+            std::process::exit(1);
+        }))
+    };
+
+    // ── TODO markers ──────────────────────────────────────────────────────────
+    let todo_scanner = if todos {
+        let cfg = config_file.as_ref().map(|c| c.todos.clone()).unwrap_or_default();
+        Some(todos::TodoScanner::new(&cfg.markers).unwrap_or_else(|e| {
+            eprintln!("ssp: {}", e);
+            std::process::exit(1);
+        }))
+    } else {
+        None
+    };
+
     // ── Build tree ────────────────────────────────────────────────────────────
     let walk_opts = WalkOptions {
         max_depth: args.depth,
@@ -175,18 +280,162 @@ fn main() {
         sort: sort_key,
         reverse: args.reverse,
         dirs_first: args.dirs_first && !args.no_dirs_first,
+        count_lines: args.show_lines,
+        text_only: args.text_only,
+        max_file_size: args.max_file_size,
+        // Reading file heads only pays off where the language of a file its
+        // name doesn't settle is used: analysis, packing, icons and colours.
+        sniff_languages: analyze
+            || todos
+            || args.format == OutputFormat::Pack
+            || ((args.format == OutputFormat::Tree || args.tui)
+                && (style_cfg.use_color || style_cfg.use_icons)),
         git_status: git_status.as_ref(),
+        find: finder.as_ref(),
     };
 
-    let root_node = build_tree(&target_dir, &walk_opts);
+    // ── ssp diff ──────────────────────────────────────────────────────────────
+    if let Some(Command::Diff(diff_args)) = &args.command {
+        let final_output = run_diff(diff_args, &args, &walk_opts, &style_cfg, &palette);
+        write_output(&final_output, args.output_file.as_deref());
+        return;
+    }
+
+    let mut root_node = match snapshot {
+        Some((imported, _)) => walk::filter_tree(imported, &walk_opts),
+        None => build_tree(&target_dir, &walk_opts),
+    };
+
+    if let Some(re) = &grep_re {
+        grep::grep_tree(&mut root_node, re, args.max_file_size);
+    }
+
+    // ── Hashes / duplicates ───────────────────────────────────────────────────
+    if let Some(algo) = args.hash
+        && args.from_json.is_none()
+    {
+        hash::hash_tree(&mut root_node, algo);
+    }
+    // Finding duplicates reads the files, which a snapshot doesn't have.
+    if args.duplicates && args.from_json.is_some() {
+        eprintln!("ssp: --duplicates is ignored with --from-json");
+    }
+    let duplicates = (args.duplicates && args.from_json.is_none()).then(|| {
+        hash::find_duplicates(&root_node, args.hash.unwrap_or(HashAlgo::Blake3))
+    });
+
+    // ── --tui ─────────────────────────────────────────────────────────────────
+    if args.tui {
+        let tui_opts = tui::TuiOptions {
+            show_hidden: walk_opts.show_hidden,
+            respect_gitignore: walk_opts.respect_gitignore,
+            show_sizes: args.sizes,
+            picker: args.print_selected,
+        };
+        let result = tui::run(root_node, tui_opts, &theme.colors, style_cfg.use_color, |hidden, gitignore| {
+            let opts = WalkOptions {
+                show_hidden: hidden,
+                respect_gitignore: gitignore,
+                ..walk_opts
+            };
+            let mut root = build_tree(&target_dir, &opts);
+            if let Some(re) = &grep_re {
+                grep::grep_tree(&mut root, re, args.max_file_size);
+            }
+            root
+        });
+        match result {
+            Ok(Some(path)) if args.print_selected => println!("{}", path.display()),
+            Ok(_) if args.print_selected => std::process::exit(1),
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("ssp: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // ── --watch ───────────────────────────────────────────────────────────────
+    if args.watch {
+        if args.format != OutputFormat::Tree {
+            eprintln!("ssp: --watch only supports --format tree");
+            std::process::exit(1);
+        }
+        let mut render_opts = RenderOptions {
+            show_lines: args.show_lines,
+            show_sizes: args.sizes,
+            show_git: args.git,
+            show_code: false,
+            analyze: false,
+            complexity: false,
+            annotate: None,
+            full_path: args.full_path,
+            root_dir: target_dir.clone(),
+            highlight: HashSet::new(),
+            find: finder.clone(),
+            grep: None,
+            grep_context: None,
+            max_file_size: args.max_file_size,
+            todos: None,
+        };
+        let result = watch::run(root_node, &walk_opts, |node, highlight| {
+            render_opts.highlight = highlight.clone();
+            let render_ctx = RenderContext {
+                style: &style_cfg,
+                palette: &palette,
+                opts: &render_opts,
+            };
+            let mut out = render_tree(
+                node,
+                &render_ctx,
+                &mut analyze::CodeStats::default(),
+                &mut Vec::new(),
+            );
+            if args.summary {
+                out.push_str(&Summary::from_node(node).format());
+                out.push('\n');
+            }
+            out
+        });
+        if let Err(e) = result {
+            eprintln!("ssp: {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // ── Analysis ──────────────────────────────────────────────────────────────
+    let mut stats = analyze::CodeStats::default();
+    if analyze {
+        analyze::analyze_tree(&root_node, args.max_file_size, &mut stats);
+    }
+    let top = (args.analyze == Some(AnalyzeMode::Complexity)).then_some(args.top);
+    let analyze_format = args.analyze_format.unwrap_or(match args.format {
+        OutputFormat::Json => AnalyzeFormat::Json,
+        OutputFormat::Markdown => AnalyzeFormat::Markdown,
+        _ => AnalyzeFormat::Text,
+    });
+    // JSON embeds the report; HTML and diagrams have nowhere to put one, so
+    // it goes to stderr instead.
+    let embedded = args.format == OutputFormat::Json && analyze_format == AnalyzeFormat::Json;
+    let report_to_stderr = !embedded
+        && matches!(
+            args.format,
+            OutputFormat::Json | OutputFormat::Html | OutputFormat::Dot | OutputFormat::Mermaid
+        );
 
     // ── Format output ─────────────────────────────────────────────────────────
     let mut final_output = String::new();
 
     match args.format {
         OutputFormat::Json => {
-            let val = to_json(&root_node, args.sizes);
-            match serde_json::to_string_pretty(&val) {
+            let json_opts = JsonOptions::new(&walk_opts, args.sizes, args.git, args.hash);
+            let mut doc = to_json(&root_node, &target_dir, json_opts, duplicates.as_deref());
+            if analyze && embedded && args.analyze_output.is_none() {
+                doc.analysis = Some(analyze::AnalysisReport::new(&stats, args.analyze_sort, top, &target_dir));
+            }
+            match serde_json::to_string_pretty(&doc) {
                 Ok(s) => final_output.push_str(&s),
                 Err(e) => {
                     eprintln!("ssp: JSON serialization error: {}", e);
@@ -196,10 +445,145 @@ fn main() {
             final_output.push('\n');
         }
         OutputFormat::Markdown => {
-            final_output.push_str(&to_markdown(&root_node, 0));
+            // The `tree` style embeds the terminal rendering minus colors and icons.
+            let plain_tree = (args.md_style == MarkdownStyle::Tree).then(|| {
+                let plain_style = StyleConfig {
+                    use_color: false,
+                    use_icons: false,
+                    ascii: style_cfg.ascii,
+                };
+                let render_opts = RenderOptions {
+                    show_lines: args.show_lines,
+                    show_sizes: args.sizes,
+                    show_git: args.git,
+                    show_code: false,
+                    analyze: false,
+                    complexity: false,
+                    annotate: None,
+                    full_path: args.full_path,
+                    root_dir: target_dir.clone(),
+                    highlight: HashSet::new(),
+                    find: finder.clone(),
+                    grep: None,
+                    grep_context: None,
+                    max_file_size: args.max_file_size,
+                    todos: None,
+                };
+                let render_ctx = RenderContext {
+                    style: &plain_style,
+                    palette: &Palette::default(),
+                    opts: &render_opts,
+                };
+                let mut tree = render_tree(
+                    &root_node,
+                    &render_ctx,
+                    &mut analyze::CodeStats::default(),
+                    &mut Vec::new(),
+                );
+                if args.summary {
+                    tree.push_str(&Summary::from_node(&root_node).format());
+                    tree.push('\n');
+                }
+                tree
+            });
+            let code = show_code.then(|| embedded_code(&root_node, &args, redactor.as_ref(), &target_dir));
+            let md_opts = MarkdownOptions {
+                style: args.md_style,
+                root_dir: &target_dir,
+                show_sizes: args.sizes,
+                show_lines: args.show_lines,
+                show_git: args.git,
+                code: code.as_ref(),
+                plain_tree: plain_tree.as_deref(),
+            };
+            final_output.push_str(&to_markdown(&root_node, &md_opts));
+        }
+        OutputFormat::Html => {
+            let code = show_code.then(|| embedded_code(&root_node, &args, redactor.as_ref(), &target_dir));
+            let html_opts = html::HtmlOptions {
+                show_sizes: args.sizes,
+                show_lines: args.show_lines,
+                show_git: args.git,
+                code: code.as_ref(),
+                root_dir: &target_dir,
+            };
+            final_output.push_str(&html::to_html(&root_node, &html_opts, &theme.colors));
+        }
+        OutputFormat::Dot | OutputFormat::Mermaid => {
+            let diagram_opts = diagram::DiagramOptions {
+                show_sizes: args.sizes,
+                show_lines: args.show_lines,
+                cluster_min: args.cluster,
+            };
+            let s = if args.format == OutputFormat::Dot {
+                diagram::to_dot(&root_node, &diagram_opts, &theme.colors)
+            } else {
+                diagram::to_mermaid(&root_node, &diagram_opts, &theme.colors)
+            };
+            final_output.push_str(&s);
+        }
+        OutputFormat::Pack => {
+            // Plain tree, then every file below it, packed or listed as skipped.
+            let plain_style = StyleConfig {
+                use_color: false,
+                use_icons: false,
+                ascii: style_cfg.ascii,
+            };
+            let render_opts = RenderOptions {
+                show_lines: args.show_lines,
+                show_sizes: args.sizes,
+                show_git: args.git,
+                show_code: false,
+                analyze: false,
+                complexity: false,
+                annotate: None,
+                full_path: args.full_path,
+                root_dir: target_dir.clone(),
+                highlight: HashSet::new(),
+                find: None,
+                grep: None,
+                grep_context: None,
+                max_file_size: args.max_file_size,
+                todos: None,
+            };
+            let render_ctx = RenderContext {
+                style: &plain_style,
+                palette: &Palette::default(),
+                opts: &render_opts,
+            };
+            let tree = render_tree(
+                &root_node,
+                &render_ctx,
+                &mut analyze::CodeStats::default(),
+                &mut Vec::new(),
+            );
+            let mut files = match args.from_json {
+                Some(_) => Vec::new(),
+                None => pack::collect_files(&root_node, args.max_file_size),
+            };
+            if let Some(r) = &redactor {
+                let texts = files
+                    .iter_mut()
+                    .filter_map(|f| Some((f.path.as_path(), f.content.as_mut().ok()?)));
+                redact_files(texts, r, &target_dir);
+            }
+            let pack_opts = pack::PackOptions {
+                max_bytes: args.max_bytes,
+                max_tokens: args.max_tokens,
+            };
+            final_output.push_str(&pack::to_pack(
+                root_node.name(),
+                &tree,
+                &files,
+                &target_dir,
+                &pack_opts,
+            ));
         }
         OutputFormat::List => {
-            let paths = to_flat_list(&root_node, &target_dir);
+            let paths = match &finder {
+                Some(m) => output::to_ranked_list(&root_node, &target_dir, m),
+                None => to_flat_list(&root_node, &target_dir),
+            };
             for p in paths {
                 final_output.push_str(&p);
                 final_output.push('\n');
@@ -211,9 +595,17 @@ fn main() {
                 show_sizes: args.sizes,
                 show_git: args.git,
                 show_code,
-                analyze: args.analyze,
+                analyze,
+                complexity: args.analyze == Some(AnalyzeMode::Complexity),
+                annotate: args.annotate,
                 full_path: args.full_path,
                 root_dir: target_dir.clone(),
+                highlight: HashSet::new(),
+                find: finder.clone(),
+                grep: grep_re.clone(),
+                grep_context: args.grep_context,
+                max_file_size: args.max_file_size,
+                todos: todo_scanner,
             };
             let render_ctx = RenderContext {
                 style: &style_cfg,
@@ -221,7 +613,6 @@ fn main() {
                 opts: &render_opts,
             };
 
-            let mut stats = analyze::CodeStats::default();
             let mut code_files: Vec<(std::path::PathBuf, String)> = Vec::new();
 
             let tree_str = render_tree(&root_node, &render_ctx, &mut stats, &mut code_files);
@@ -234,14 +625,46 @@ fn main() {
                 final_output.push('\n');
             }
 
+            // Duplicates section
+            if let Some(groups) = &duplicates {
+                final_output.push_str("\n\n=== DUPLICATES ===\n\n");
+                final_output.push_str(&hash::format_duplicates(groups, &target_dir));
+            }
+
+            // TODO markers section
+            if let Some(scanner) = &render_opts.todos {
+                if args.git {
+                    todos::add_authors(&mut stats.todos);
+                }
+                final_output.push_str(&format!("\n\n=== TODOS ({}) ===\n\n", stats.todos.len()));
+                final_output.push_str(&scanner.format_list(&stats.todos, &target_dir));
+            }
+
             // Code content section
+            if let Some(r) = redactor.as_ref().filter(|_| show_code) {
+                let texts = code_files.iter_mut().map(|(p, c)| (p.as_path(), c));
+                redact_files(texts, r, &target_dir);
+            }
             if show_code && !code_files.is_empty() {
+                // Highlighting only when colored; piped output stays plain.
+                let highlighter = if style_cfg.use_color {
+                    highlight::CodeHighlighter::new(&theme.colors.code_theme)
+                        .map_err(|e| eprintln!("ssp: {}", e))
+                        .ok()
+                } else {
+                    None
+                };
                 final_output.push_str("\n\n=== CODE CONTENT ===\n\n");
                 for (idx, (path, content)) in code_files.iter().enumerate() {
                     let rel = path
                         .strip_prefix(&target_dir)
                         .unwrap_or(path)
                         .to_string_lossy();
+                    if let Some(h) = &highlighter {
+                        final_output.push_str(&h.render_file(idx + 1, &rel, path, content, &palette));
+                        final_output.push('\n');
+                        continue;
+                    }
                     final_output.push_str(&format!("{}. {}:\n\n", idx + 1, rel));
                     final_output.push_str(content);
                     final_output.push_str("\n\n");
@@ -249,17 +672,72 @@ fn main() {
                     final_output.push_str("\n\n");
                 }
             }
+        }
+    }
 
-            // Analysis section (render_tree already collected stats when analyze=true)
-            if args.analyze {
+    // ── Analysis report ───────────────────────────────────────────────────────
+    if analyze && !(embedded && args.analyze_output.is_none()) {
+        let report = analyze::AnalysisReport::new(&stats, args.analyze_sort, top, &target_dir);
+        let rendered = report.render(analyze_format).unwrap_or_else(|e| {
+            eprintln!("ssp: JSON serialization error: {}", e);
+            std::process::exit(1);
+        });
+        if let Some(file) = &args.analyze_output {
+            write_output(&rendered, Some(file));
+        } else if report_to_stderr {
+            eprint!("{}", rendered);
+        } else {
+            if args.format == OutputFormat::Tree {
                 final_output.push_str("\n\n=== CODE ANALYSIS ===\n\n");
-                final_output.push_str(&analyze::format_analysis(&stats));
+            } else if !final_output.is_empty() {
+                final_output.push('\n');
             }
+            final_output.push_str(&rendered);
         }
     }
 
     // ── Write output ──────────────────────────────────────────────────────────
-    if let Some(filename) = &args.output_file {
+    write_output(&final_output, args.output_file.as_deref());
+}
+
+/// Mask secrets in collected file contents and report what was masked on
+/// stderr, so the report never ends up in the output itself.
+fn redact_files<'a>(
+    files: impl IntoIterator<Item = (&'a Path, &'a mut String)>,
+    redactor: &redact::Redactor,
+    base: &Path,
+) {
+    let mut found = Vec::new();
+    for (path, content) in files {
+        let (masked, hits) = redactor.redact(path, content);
+        if !hits.is_empty() {
+            *content = masked;
+            found.extend(hits);
+        }
+    }
+    if !found.is_empty() {
+        eprint!("{}", redact::format_report(&found, base));
+    }
+}
+
+/// `--show-code` contents for the HTML and Markdown formats, redacted
+/// unless `--no-redact`, with what was masked reported on stderr.
+fn embedded_code(
+    root: &tree::Node,
+    args: &Args,
+    redactor: Option<&redact::Redactor>,
+    base: &Path,
+) -> HashMap<PathBuf, String> {
+    let (code, found) = redact::embedded_code(root, args.max_file_size, redactor);
+    if !found.is_empty() {
+        eprint!("{}", redact::format_report(&found, base));
+    }
+    code
+}
+
+/// Print to stdout, or write to `-o FILE` with a confirmation on stderr.
+fn write_output(final_output: &str, output_file: Option<&str>) {
+    if let Some(filename) = output_file {
         match std::fs::File::create(filename) {
             Ok(mut file) => {
                 if let Err(e) = file.write_all(final_output.as_bytes()) {
@@ -278,3 +756,80 @@ fn main() {
         print!("{}", final_output);
     }
 }
+
+/// `ssp stats`: measure past revisions and render the trend.
+fn run_stats(stats_args: &StatsArgs, args: &Args, style_cfg: &StyleConfig) -> String {
+    let dir = PathBuf::from(stats_args.directory.as_deref().unwrap_or("."));
+    let points = history::collect(&dir, &stats_args.at, stats_args.history, stats_args.max_file_size)
+        .unwrap_or_else(|e| {
+            eprintln!("ssp: {}", e);
+            std::process::exit(1);
+        });
+
+    match args.format {
+        OutputFormat::Json => match serde_json::to_string_pretty(&history::to_json(points, &dir)) {
+            Ok(s) => s + "\n",
+            Err(e) => {
+                eprintln!("ssp: JSON serialization error: {}", e);
+                std::process::exit(1);
+            }
+        },
+        OutputFormat::Tree if stats_args.sparkline => history::format_sparklines(&points, style_cfg.ascii),
+        OutputFormat::Tree => history::format_table(&points),
+        _ => {
+            eprintln!("ssp: stats supports --format tree or --format json");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// `ssp diff OLD NEW`: build both trees and render the merged result.
+fn run_diff(
+    diff_args: &DiffArgs,
+    args: &Args,
+    walk_opts: &WalkOptions,
+    style_cfg: &StyleConfig,
+    palette: &Palette,
+) -> String {
+    let load = |side: &str| {
+        diff::load_side(Path::new(side), walk_opts, args.hash).unwrap_or_else(|e| {
+            eprintln!("ssp: {}", e);
+            std::process::exit(1);
+        })
+    };
+    let old = load(&diff_args.old);
+    let new = load(&diff_args.new);
+
+    let cmp = diff::CompareOptions {
+        mtime: diff_args.mtime,
+        content: diff_args.content,
+    };
+    let merged = diff::diff_trees(&old, &new, cmp);
+
+    match args.format {
+        OutputFormat::Json => {
+            let doc = diff::to_json(&merged, Path::new(&diff_args.old), Path::new(&diff_args.new));
+            match serde_json::to_string_pretty(&doc) {
+                Ok(s) => s + "\n",
+                Err(e) => {
+                    eprintln!("ssp: JSON serialization error: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        OutputFormat::Tree => {
+            let render_opts = diff::DiffRenderOptions {
+                show_sizes: args.sizes,
+                changes_only: diff_args.changes_only,
+            };
+            let mut out = diff::render_diff(&merged, style_cfg, palette, &render_opts);
+            out.push_str(&diff::DiffSummary::from_diff(&merged).format());
+            out.push('\n');
+            out
+        }
+        _ => {
+            eprintln!("ssp: diff supports --format tree or --format json");
+            std::process::exit(1);
+        }
+    }
+}
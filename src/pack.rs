@@ -0,0 +1,393 @@
+//! `--format pack`: the tree plus file contents in a delimited layout meant
+//! for feeding a project to a code assistant.
+//!
+//! ```text
+//! <pack root="ssp" files="12">
+//! <tree>
+//! …plain tree…
+//! </tree>
+//! <file path="src/main.rs" language="rust" size="1834" lines="61"><![CDATA[…content…
+//! ]]></file>
+//! <file path="src/big.rs" … truncated="true" shown_lines="120"><![CDATA[…
+//! ]]>
+//! [… truncated: 80 of 200 lines omitted …]
+//! </file>
+//! <skipped path="Cargo.lock" reason="lockfile"/>
+//! <skipped path="logo.png" reason="binary"/>
+//! <summary included="11" truncated="1" skipped="1" bytes="53211" tokens="13303"/>
+//! </pack>
+//! ```
+//!
+//! Attribute values are XML-escaped; contents go in CDATA sections, so a
+//! file holding `</file>` can't end its block early.
+
+use std::path::{Path, PathBuf};
+
+use crate::content::{self, Content};
+use crate::html::escape;
+use crate::lang::{Category, Language};
+use crate::tree::{Node, NodeKind};
+
+/// Don't bother truncating a file to less than this; skip it instead.
+const MIN_TRUNCATED_BYTES: u64 = 1024;
+
+/// With a budget, no single file may take more than this share of it, so
+/// one long README or generated table can't crowd out the source.
+const MAX_FILE_SHARE: u64 = 4;
+
+/// Rough bytes-per-token ratio for source code.
+const BYTES_PER_TOKEN: u64 = 4;
+
+pub struct PackOptions {
+    /// Content budget in bytes.
+    pub max_bytes: Option<u64>,
+    /// Content budget in (estimated) tokens.
+    pub max_tokens: Option<u64>,
+}
+
+impl PackOptions {
+    fn budget(&self) -> Option<u64> {
+        let from_tokens = self.max_tokens.map(|t| t * BYTES_PER_TOKEN);
+        match (self.max_bytes, from_tokens) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// One file of the tree, as it goes into the pack.
+pub struct PackFile {
+    pub path: PathBuf,
+    pub lang: Option<&'static Language>,
+    /// The text, or why there is none: `"size"` (above `--max-file-size`),
+    /// `"binary"` or `"unreadable"`.
+    pub content: Result<String, &'static str>,
+}
+
+/// Every file below `node`, in tree order, with the text of those that are
+/// readable, not binary and within `max_size`.
+pub fn collect_files(node: &Node, max_size: Option<u64>) -> Vec<PackFile> {
+    let mut files = Vec::new();
+    collect(node, max_size, &mut files);
+    files
+}
+
+fn collect(node: &Node, max_size: Option<u64>, files: &mut Vec<PackFile>) {
+    match node.kind {
+        NodeKind::Dir => {
+            for child in &node.children {
+                collect(child, max_size, files);
+            }
+        }
+        NodeKind::File => {
+            let content = if max_size.is_some_and(|max| node.size > max) {
+                Err("size")
+            } else if node.binary {
+                Err("binary")
+            } else {
+                match content::read(&node.path) {
+                    Ok(Content::Text { text, .. }) => Ok(text),
+                    Ok(Content::Binary) => Err("binary"),
+                    Err(_) => Err("unreadable"),
+                }
+            };
+            files.push(PackFile {
+                path: node.path.clone(),
+                lang: node.lang,
+                content,
+            });
+        }
+        NodeKind::Symlink => {}
+    }
+}
+
+/// Estimated token count for `bytes` of text.
+pub fn estimate_tokens(bytes: u64) -> u64 {
+    bytes.div_ceil(BYTES_PER_TOKEN)
+}
+
+/// Why a file is left out of the pack regardless of budget.
+pub fn skip_reason(path: &Path) -> Option<&'static str> {
+    let name = path.file_name()?.to_str()?.to_lowercase();
+    const LOCKFILES: &[&str] = &[
+        "cargo.lock", "package-lock.json", "npm-shrinkwrap.json", "yarn.lock",
+        "pnpm-lock.yaml", "bun.lockb", "poetry.lock", "pipfile.lock", "uv.lock",
+        "gemfile.lock", "composer.lock", "go.sum", "flake.lock", "mix.lock",
+        "packages.lock.json", "podfile.lock",
+    ];
+    if LOCKFILES.contains(&name.as_str()) {
+        return Some("lockfile");
+    }
+    const GENERATED_SUFFIXES: &[&str] = &[
+        ".min.js", ".min.css", ".map", ".pb.go", "_pb2.py", "_pb2_grpc.py",
+        ".pb.cc", ".pb.h", ".g.dart", ".freezed.dart", ".designer.cs",
+    ];
+    if GENERATED_SUFFIXES.iter().any(|s| name.ends_with(s)) || name.contains(".generated.") {
+        return Some("generated");
+    }
+    None
+}
+
+/// Lower sorts first: docs and manifests, then source, then everything
+/// else; within a class, shallow before deep and small before large.
+fn priority(rel: &str, path: &Path, lang: Option<&Language>, size: u64) -> (u8, usize, u64) {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    const MANIFESTS: &[&str] = &[
+        "cargo.toml", "package.json", "pyproject.toml", "setup.py", "go.mod",
+        "pom.xml", "build.gradle", "build.gradle.kts", "gemfile", "composer.json",
+        "cmakelists.txt", "makefile", "dockerfile",
+    ];
+    let is_source = lang.is_some_and(|l| matches!(l.category, Category::Programming | Category::Markup));
+    let class = if name.starts_with("readme") {
+        0
+    } else if MANIFESTS.contains(&name.as_str()) {
+        1
+    } else if is_source {
+        2
+    } else {
+        3
+    };
+    (class, rel.matches('/').count(), size)
+}
+
+enum Fit {
+    Full,
+    /// Number of leading lines that fit.
+    Truncated(usize),
+    Omitted,
+}
+
+/// Build the pack from the plain tree rendering and the files from
+/// `collect_files`.
+pub fn to_pack(
+    root_name: &str,
+    tree: &str,
+    files: &[PackFile],
+    base: &Path,
+    opts: &PackOptions,
+) -> String {
+    let rel = |p: &Path| {
+        p.strip_prefix(base)
+            .unwrap_or(p)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
+    };
+
+    // Decide what fits, most important files first.
+    let mut fits: Vec<Fit> = files.iter().map(|_| Fit::Omitted).collect();
+    let mut order: Vec<(usize, &str)> = files
+        .iter()
+        .enumerate()
+        .filter(|(_, f)| skip_reason(&f.path).is_none())
+        .filter_map(|(i, f)| Some((i, f.content.as_deref().ok()?)))
+        .collect();
+    order.sort_by_key(|&(i, text)| {
+        let file = &files[i];
+        priority(&rel(&file.path), &file.path, file.lang, text.len() as u64)
+    });
+    let budget = opts.budget();
+    let per_file = budget.map(|b| (b / MAX_FILE_SHARE).max(MIN_TRUNCATED_BYTES));
+    let mut remaining = budget;
+    for (i, text) in order {
+        let len = text.len() as u64;
+        let (Some(rem), Some(cap)) = (remaining, per_file) else {
+            fits[i] = Fit::Full;
+            continue;
+        };
+        let allowance = rem.min(cap);
+        fits[i] = if len <= allowance {
+            remaining = Some(rem - len);
+            Fit::Full
+        } else if allowance >= MIN_TRUNCATED_BYTES {
+            let (lines, used) = lines_within(text, allowance);
+            remaining = Some(rem - used);
+            Fit::Truncated(lines)
+        } else {
+            Fit::Omitted
+        };
+    }
+
+    let mut out = format!("<pack root=\"{}\" files=\"{}\">\n", escape(root_name), files.len());
+    out.push_str("<tree>\n");
+    out.push_str(tree);
+    if !tree.ends_with('\n') {
+        out.push('\n');
+    }
+    out.push_str("</tree>\n");
+
+    let (mut included, mut truncated, mut skipped, mut bytes) = (0, 0, 0, 0u64);
+    for (file, fit) in files.iter().zip(&fits) {
+        let path_attr = escape(&rel(&file.path));
+        let content = match (skip_reason(&file.path), &file.content) {
+            (None, Ok(text)) => text,
+            (Some(reason), _) | (None, &Err(reason)) => {
+                out.push_str(&format!("<skipped path=\"{}\" reason=\"{}\"/>\n", path_attr, reason));
+                skipped += 1;
+                continue;
+            }
+        };
+        let total_lines = content.lines().count();
+        let lang_attr = match file.lang {
+            Some(lang) => format!(" language=\"{}\"", lang.id),
+            None => String::new(),
+        };
+        let head = format!(
+            "<file path=\"{}\"{} size=\"{}\" lines=\"{}\"",
+            path_attr,
+            lang_attr,
+            content.len(),
+            total_lines
+        );
+        match fit {
+            Fit::Full => {
+                out.push_str(&format!("{}>{}</file>\n", head, cdata(content)));
+                included += 1;
+                bytes += content.len() as u64;
+            }
+            Fit::Truncated(n) => {
+                let mut shown = String::new();
+                for line in content.lines().take(*n) {
+                    shown.push_str(line);
+                    shown.push('\n');
+                }
+                bytes += shown.len() as u64;
+                out.push_str(&format!(
+                    "{} truncated=\"true\" shown_lines=\"{}\">{}\n",
+                    head,
+                    n,
+                    cdata(&shown)
+                ));
+                out.push_str(&format!(
+                    "[… truncated: {} of {} lines omitted …]\n</file>\n",
+                    total_lines - n,
+                    total_lines
+                ));
+                included += 1;
+                truncated += 1;
+            }
+            Fit::Omitted => {
+                out.push_str(&format!("<skipped path=\"{}\" reason=\"budget\"/>\n", path_attr));
+                skipped += 1;
+            }
+        }
+    }
+
+    out.push_str(&format!(
+        "<summary included=\"{}\" truncated=\"{}\" skipped=\"{}\" bytes=\"{}\" tokens=\"{}\"/>\n",
+        included,
+        truncated,
+        skipped,
+        bytes,
+        estimate_tokens(bytes)
+    ));
+    out.push_str("</pack>\n");
+    out
+}
+
+/// `text` as a CDATA section; a `]]>` inside it is split across two.
+fn cdata(text: &str) -> String {
+    format!("<![CDATA[{}]]>", text.replace("]]>", "]]]]><![CDATA[>"))
+}
+
+/// How many leading lines of `content` fit in `budget` bytes, and their size.
+fn lines_within(content: &str, budget: u64) -> (usize, u64) {
+    let mut used = 0;
+    let mut count = 0;
+    for line in content.lines() {
+        let len = line.len() as u64 + 1;
+        if used + len > budget {
+            break;
+        }
+        used += len;
+        count += 1;
+    }
+    (count, used)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::{PackFile, PackOptions, collect_files, skip_reason, to_pack};
+    use crate::lang;
+    use crate::walk::{WalkOptions, build_tree};
+
+    fn file(path: &str, content: &str) -> PackFile {
+        PackFile {
+            path: path.into(),
+            lang: lang::detect(Path::new(path), None),
+            content: Ok(content.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_pack_budget() {
+        let base = Path::new("/p");
+        let big = "let x = 1;\n".repeat(300); // 3300 bytes
+        let files = [
+            file("/p/Cargo.lock", "# lock\n"),
+            file("/p/README.md", "# Demo\n"),
+            file("/p/src/deep/big.rs", &big),
+            file("/p/src/main.rs", "fn main() {}\n"),
+        ];
+        let opts = PackOptions {
+            max_bytes: Some(8192),
+            max_tokens: None,
+        };
+        let out = to_pack("p", "p/\n", &files, base, &opts);
+
+        assert!(out.contains("<skipped path=\"Cargo.lock\" reason=\"lockfile\"/>"));
+        assert!(out.contains("<file path=\"README.md\" language=\"markdown\" size=\"7\" lines=\"1\"><![CDATA[# Demo\n]]></file>"));
+        assert!(out.contains("<file path=\"src/main.rs\" language=\"rust\""));
+        // No file may take more than a quarter of the budget.
+        assert!(out.contains("truncated=\"true\" shown_lines=\"186\""));
+        assert!(out.contains("let x = 1;\n]]>\n[… truncated: 114 of 300 lines omitted …]\n</file>"));
+        assert!(out.contains("<summary included=\"3\" truncated=\"1\" skipped=\"1\""));
+
+        assert_eq!(skip_reason(Path::new("web/app.min.js")), Some("generated"));
+        assert_eq!(skip_reason(Path::new("src/lib.rs")), None);
+    }
+
+    #[test]
+    fn test_pack_delimits_contents() {
+        let files = [file("/p/notes.txt", "</file>\n<skipped path=\"x\"/>\na]]>b\n</pack>")];
+        let opts = PackOptions {
+            max_bytes: None,
+            max_tokens: None,
+        };
+        let out = to_pack("p", "p/\n", &files, Path::new("/p"), &opts);
+
+        assert!(out.contains(
+            "<![CDATA[</file>\n<skipped path=\"x\"/>\na]]]]><![CDATA[>b\n</pack>]]></file>\n<summary"
+        ));
+        assert_eq!(out.matches("</file>").count(), 2);
+        assert!(out.ends_with("skipped=\"0\" bytes=\"41\" tokens=\"11\"/>\n</pack>\n"));
+    }
+
+    #[test]
+    fn test_pack_lists_unpacked_files() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.rs"), "fn a() {}\n").unwrap();
+        std::fs::write(dir.path().join("big.txt"), "x\n".repeat(100)).unwrap();
+        std::fs::write(dir.path().join("logo.png"), b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR").unwrap();
+
+        let tree = build_tree(dir.path(), &WalkOptions::default());
+        let files = collect_files(&tree, Some(50));
+        let opts = PackOptions {
+            max_bytes: None,
+            max_tokens: None,
+        };
+        let out = to_pack("p", "p/\n", &files, dir.path(), &opts);
+
+        assert!(out.starts_with("<pack root=\"p\" files=\"3\">"));
+        assert!(out.contains("<file path=\"a.rs\" language=\"rust\" size=\"10\" lines=\"1\">"));
+        assert!(out.contains("<skipped path=\"big.txt\" reason=\"size\"/>"));
+        assert!(out.contains("<skipped path=\"logo.png\" reason=\"binary\"/>"));
+        assert!(out.contains("<summary included=\"1\" truncated=\"0\" skipped=\"2\""));
+    }
+}
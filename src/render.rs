@@ -1,8 +1,19 @@
 //! Tree-format renderer: walks the in-memory Node tree and produces a String.
 
-use crate::analyze::{self, CodeStats};
-use crate::style::{Palette, StyleConfig, git_status_glyph, icon_str, paint_connector,
-                   paint_meta, paint_name};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+use regex::Regex;
+
+use crate::analyze::{self, CodeStats, LanguageStats};
+use crate::cli::Annotate;
+use crate::content::{self, Content, Encoding};
+use crate::grep::{ContextLine, context_lines};
+use crate::hash::short_digest;
+use crate::search::Matcher;
+use crate::style::{Palette, StyleConfig, git_status_glyph, icon_str, name_style, paint_connector,
+                   paint_highlight, paint_meta, paint_name};
+use crate::todos::TodoScanner;
 use crate::tree::{Node, NodeKind, human_size};
 
 pub struct RenderOptions {
@@ -10,9 +21,28 @@ pub struct RenderOptions {
     pub show_sizes: bool,
     pub show_git: bool,
     pub show_code: bool,
+    /// Annotate with the per-file results `analyze::analyze_tree` left in
+    /// the `CodeStats` passed to `render_tree`.
     pub analyze: bool,
+    /// Annotate files with their most complex function's complexity.
+    pub complexity: bool,
+    /// `--annotate`: line counts or comment density on files, subtotals on
+    /// directories.
+    pub annotate: Option<Annotate>,
     pub full_path: bool,
     pub root_dir: std::path::PathBuf,
+    /// Entries to emphasize (recently changed ones in `--watch`).
+    pub highlight: HashSet<PathBuf>,
+    /// `--find` query whose matched characters are highlighted.
+    pub find: Option<Matcher>,
+    /// `--grep` pattern, used to mark matches in context lines.
+    pub grep: Option<Regex>,
+    /// `--grep-context`: print matching lines (plus N around them) under each file.
+    pub grep_context: Option<usize>,
+    /// `--max-file-size`: larger files are not read and get a `[skipped]` marker.
+    pub max_file_size: Option<u64>,
+    /// `--todos`: count markers per file, collected into `CodeStats::todos`.
+    pub todos: Option<TodoScanner>,
 }
 
 pub struct RenderContext<'a> {
@@ -21,7 +51,8 @@ pub struct RenderContext<'a> {
     pub opts: &'a RenderOptions,
 }
 
-/// Render the root node into a tree string, populate `stats` and `code_files`.
+/// Render the root node into a tree string, adding `--todos` markers to
+/// `stats` and file contents to `code_files`.
 pub fn render_tree(
     root: &Node,
     ctx: &RenderContext,
@@ -32,23 +63,33 @@ pub fn render_tree(
 
     // Root line
     let root_name = root.name();
-    let colored_name = paint_name(root_name, root.kind, ctx.palette);
-    let icon = icon_str(root_name, root.kind, ctx.style);
+    let root_style = name_style(root.kind, &root.path, root.lang, root.is_exec, ctx.palette);
+    let colored_name = paint_name(root_name, root_style, &[], ctx.palette);
+    let icon = icon_str(root_name, root.lang, root.kind, ctx.style);
     let icon_prefix = if ctx.style.use_icons && !icon.is_empty() {
         format!("{} ", icon)
     } else {
         String::new()
     };
-    out.push_str(&format!("{}{}/\n", icon_prefix, colored_name));
-
+    // Children first, so the root line can carry their subtotal.
+    let mut body = String::new();
+    let mut counts = LanguageStats::default();
     for (i, child) in root.children.iter().enumerate() {
         let is_last = i == root.children.len() - 1;
-        render_node(child, "", is_last, ctx, stats, code_files, &mut out);
+        counts.add(&render_node(child, "", is_last, ctx, stats, code_files, &mut body));
     }
+    let annotation = ctx.opts.annotate
+        .and_then(|what| analyze::annotation(&counts, what))
+        .map(|a| format!(" {}", paint_meta(&format!("({})", a), ctx.palette)))
+        .unwrap_or_default();
+    out.push_str(&format!("{}{}/{}\n", icon_prefix, colored_name, annotation));
+    out.push_str(&body);
 
     out
 }
 
+/// Render `node` and everything below it, and return the line counts of
+/// the files analyzed there (for directory subtotals).
 fn render_node(
     node: &Node,
     prefix: &str,
@@ -57,7 +98,7 @@ fn render_node(
     stats: &mut CodeStats,
     code_files: &mut Vec<(std::path::PathBuf, String)>,
     out: &mut String,
-) {
+) -> LanguageStats {
     let connector_raw = if is_last {
         ctx.style.branch_last()
     } else {
@@ -74,38 +115,81 @@ fn render_node(
         node.name().to_string()
     };
 
-    let icon = icon_str(&display_name, node.kind, ctx.style);
+    let icon = icon_str(node.name(), node.lang, node.kind, ctx.style);
     let icon_prefix = if ctx.style.use_icons && !icon.is_empty() {
         format!("{} ", icon)
     } else {
         String::new()
     };
-    let name_colored = paint_name(&display_name, node.kind, ctx.palette);
+    let highlighted = ctx.opts.highlight.contains(&node.path);
+    let name_colored = if highlighted {
+        paint_highlight(&display_name, ctx.palette)
+    } else {
+        // Match positions refer to the bare name, which ends `display_name`.
+        let offset = display_name.chars().count() - node.name().chars().count();
+        let matched: Vec<usize> = ctx.opts.find
+            .as_ref()
+            .and_then(|m| m.find(node.name()))
+            .map(|m| m.positions.iter().map(|p| p + offset).collect())
+            .unwrap_or_default();
+        let base = name_style(node.kind, &node.path, node.lang, node.is_exec, ctx.palette);
+        paint_name(&display_name, base, &matched, ctx.palette)
+    };
     let connector = paint_connector(connector_raw, ctx.palette);
     let prefix_colored = paint_connector(prefix, ctx.palette);
 
     let dir_slash = if node.kind == NodeKind::Dir { "/" } else { "" };
 
     // ── Read file content once if any flag needs it ────────────────────────
+    let show_matches = ctx.opts.grep_context.is_some() && node.matches.is_some();
+    let todos = ctx.opts.todos.as_ref();
+    let wants_content = node.kind == NodeKind::File
+        && (ctx.opts.show_code || ctx.opts.analyze || ctx.opts.show_lines || show_matches || todos.is_some());
+    let skipped = wants_content && ctx.opts.max_file_size.is_some_and(|max| node.size > max);
     let needs_content = node.kind == NodeKind::File
-        && (ctx.opts.show_lines || ctx.opts.show_code || ctx.opts.analyze);
+        && !skipped
+        && (ctx.opts.show_code || show_matches || todos.is_some());
 
-    let file_content: Option<String> = if needs_content {
-        std::fs::read_to_string(&node.path).ok()
+    let (file_content, binary, encoding) = match needs_content.then(|| content::read(&node.path)) {
+        Some(Ok(Content::Text { text, encoding })) => (Some(text), false, Some(encoding)),
+        Some(Ok(Content::Binary)) => (None, true, None),
+        _ => (None, false, None),
+    };
+    let binary = binary || node.binary;
+
+    let segment = if is_last {
+        ctx.style.branch_blank()
     } else {
-        None
+        ctx.style.branch_pipe()
     };
+    let new_prefix = format!("{}{}", prefix, segment);
 
-    // Populate stats and code_files
+    // Populate stats and code_files; `--analyze` results come from `analyze_tree`.
+    let (mut counts, max_complexity) = match stats.files.get(&node.path) {
+        Some(file) if ctx.opts.analyze => (file.counts.clone(), file.max_complexity),
+        _ => (LanguageStats::default(), None),
+    };
+    let mut todo_count = 0;
     if let Some(ref content) = file_content {
-        if ctx.opts.analyze || ctx.opts.show_lines {
-            analyze::analyze_file(&node.path, content, stats);
+        if let Some(scanner) = todos {
+            let found = scanner.scan(&node.path, node.lang, content);
+            todo_count = found.len();
+            stats.todos.extend(found);
         }
         if ctx.opts.show_code {
             code_files.push((node.path.clone(), content.clone()));
         }
     }
 
+    // ── Render directory contents first, for their subtotal ───────────────
+    let mut children_out = String::new();
+    if node.kind == NodeKind::Dir {
+        for (i, child) in node.children.iter().enumerate() {
+            let child_last = i == node.children.len() - 1;
+            counts.add(&render_node(child, &new_prefix, child_last, ctx, stats, code_files, &mut children_out));
+        }
+    }
+
     // ── Build metadata string ──────────────────────────────────────────────
     let mut meta_parts: Vec<String> = Vec::new();
 
@@ -113,15 +197,31 @@ fn render_node(
         meta_parts.push(human_size(node.size));
     }
 
-    if ctx.opts.show_lines && node.kind == NodeKind::File {
-        let lc = file_content
-            .as_deref()
-            .map(|c| c.lines().count())
-            .unwrap_or(0);
-        meta_parts.push(format!("{} lines", lc));
+    if ctx.opts.show_lines && node.kind == NodeKind::File && !binary && !skipped {
+        meta_parts.push(format!("{} lines", node.lines.unwrap_or(0)));
+    }
+
+    if let Some(hash) = &node.hash {
+        meta_parts.push(short_digest(hash).to_string());
+    }
+
+    if let Some(n) = node.matches {
+        meta_parts.push(format!("{} {}", n, if n == 1 { "match" } else { "matches" }));
+    }
+
+    if let Some(annotation) = ctx.opts.annotate.and_then(|what| analyze::annotation(&counts, what)) {
+        meta_parts.push(annotation);
+    }
+
+    if todo_count > 0 {
+        meta_parts.push(format!("{} {}", todo_count, if todo_count == 1 { "todo" } else { "todos" }));
+    }
+
+    if let Some(cc) = max_complexity.filter(|_| ctx.opts.complexity) {
+        meta_parts.push(format!("complexity {}", cc));
     }
 
-    let meta_str = if meta_parts.is_empty() {
+    let mut meta_str = if meta_parts.is_empty() {
         String::new()
     } else {
         format!(
@@ -130,6 +230,20 @@ fn render_node(
         )
     };
 
+    // Content markers: binaries, and text decoded from something other than UTF-8.
+    let content_marker = if skipped {
+        Some(format!("skipped: {}", human_size(node.size)))
+    } else if binary {
+        Some("binary".to_string())
+    } else {
+        encoding
+            .filter(|e: &Encoding| e.is_notable())
+            .map(|e| e.name().to_string())
+    };
+    if let Some(marker) = content_marker {
+        meta_str.push_str(&format!(" {}", paint_meta(&format!("[{}]", marker), ctx.palette)));
+    }
+
     // ── Git glyph ─────────────────────────────────────────────────────────
     let git_glyph = if ctx.opts.show_git {
         node.git_xy
@@ -139,8 +253,14 @@ fn render_node(
         String::new()
     };
 
+    let marker = if highlighted {
+        format!(" {}", paint_highlight("*", ctx.palette))
+    } else {
+        String::new()
+    };
+
     out.push_str(&format!(
-        "{}{}{}{}{}{}{}\n",
+        "{}{}{}{}{}{}{}{}\n",
         prefix_colored,
         connector,
         icon_prefix,
@@ -148,20 +268,51 @@ fn render_node(
         dir_slash,
         meta_str,
         git_glyph,
+        marker,
     ));
 
-    // ── Recurse into directories ───────────────────────────────────────────
-    if node.kind == NodeKind::Dir {
-        let segment = if is_last {
-            ctx.style.branch_blank()
-        } else {
-            ctx.style.branch_pipe()
-        };
-        let new_prefix = format!("{}{}", prefix, segment);
-
-        for (i, child) in node.children.iter().enumerate() {
-            let child_last = i == node.children.len() - 1;
-            render_node(child, &new_prefix, child_last, ctx, stats, code_files, out);
+    // ── Grep context lines ─────────────────────────────────────────────────
+    if show_matches
+        && let (Some(re), Some(n), Some(content)) =
+            (&ctx.opts.grep, ctx.opts.grep_context, &file_content)
+    {
+        let indent = paint_connector(&new_prefix, ctx.palette);
+        for line in context_lines(content, re, n) {
+            match line {
+                ContextLine::Line(no, text, is_match) => {
+                    let sep = if is_match { ':' } else { '-' };
+                    let text = if is_match {
+                        paint_matches(text, re, ctx)
+                    } else {
+                        text.to_string()
+                    };
+                    out.push_str(&format!(
+                        "{}  {} {}\n",
+                        indent,
+                        paint_meta(&format!("{}{}", no, sep), ctx.palette),
+                        text
+                    ));
+                }
+                ContextLine::Separator => {
+                    out.push_str(&format!("{}  {}\n", indent, paint_meta("--", ctx.palette)));
+                }
+            }
         }
     }
+
+    out.push_str(&children_out);
+    counts
+}
+
+/// Highlight every match of `re` in a context line.
+fn paint_matches(text: &str, re: &Regex, ctx: &RenderContext) -> String {
+    let mut out = String::new();
+    let mut last = 0;
+    for m in re.find_iter(text) {
+        out.push_str(&text[last..m.start()]);
+        out.push_str(&paint_highlight(m.as_str(), ctx.palette));
+        last = m.end();
+    }
+    out.push_str(&text[last..]);
+    out
 }
@@ -0,0 +1,161 @@
+//! Name matching shared by the TUI search box and `--find`.
+
+use regex::Regex;
+
+/// A successful match: higher `score` is better; `positions` are the char
+/// indices of the matched characters in the candidate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NameMatch {
+    pub score: i64,
+    pub positions: Vec<usize>,
+}
+
+/// A compiled `--find` query.
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    Fuzzy(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    pub fn new(query: &str, regex: bool) -> Result<Self, String> {
+        if regex {
+            Regex::new(query)
+                .map(Matcher::Regex)
+                .map_err(|e| format!("invalid regex '{}': {}", query, e))
+        } else {
+            Ok(Matcher::Fuzzy(query.to_string()))
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<NameMatch> {
+        match self {
+            Matcher::Fuzzy(query) => fuzzy_match(query, name),
+            Matcher::Regex(re) => regex_match(re, name),
+        }
+    }
+}
+
+/// First regex match in `name`.  Anchored and whole-name matches rank
+/// highest; every unmatched character costs a point.
+fn regex_match(re: &Regex, name: &str) -> Option<NameMatch> {
+    let m = re.find(name)?;
+    let start = name[..m.start()].chars().count();
+    let len = m.as_str().chars().count();
+    let total = name.chars().count();
+    let mut score = 64 - (total - len) as i64;
+    if start == 0 {
+        score += 10;
+    }
+    if start + len == total {
+        score += 10;
+    }
+    Some(NameMatch {
+        score,
+        positions: (start..start + len).collect(),
+    })
+}
+
+/// Subsequence match of `query` against `candidate`, fzf-style: every query
+/// character must appear in order.  Matching is case-insensitive unless the
+/// query contains an uppercase letter.  Consecutive runs, word starts and
+/// an early first match score higher; gaps cost a little.
+pub fn fuzzy_match(query: &str, candidate: &str) -> Option<NameMatch> {
+    if query.is_empty() {
+        return Some(NameMatch { score: 0, positions: Vec::new() });
+    }
+    let case_sensitive = query.chars().any(char::is_uppercase);
+    let fold = |c: char| if case_sensitive { c } else { c.to_ascii_lowercase() };
+    let query: Vec<char> = query.chars().map(fold).collect();
+    let original: Vec<char> = candidate.chars().collect();
+    let text: Vec<char> = original.iter().copied().map(fold).collect();
+
+    // Greedy from every possible start of the first character; keep the best.
+    let mut best: Option<NameMatch> = None;
+    for start in (0..text.len()).filter(|&i| text[i] == query[0]) {
+        let mut positions = Vec::with_capacity(query.len());
+        let mut qi = 0;
+        for (ti, &c) in text.iter().enumerate().skip(start) {
+            if c == query[qi] {
+                positions.push(ti);
+                qi += 1;
+                if qi == query.len() {
+                    break;
+                }
+            }
+        }
+        if qi < query.len() {
+            // Later starts can only match less.
+            break;
+        }
+        let score = score_positions(&original, &positions);
+        if best.as_ref().is_none_or(|b| score > b.score) {
+            best = Some(NameMatch { score, positions });
+        }
+    }
+    best
+}
+
+fn score_positions(text: &[char], positions: &[usize]) -> i64 {
+    let mut score = 0i64;
+    let mut prev: Option<usize> = None;
+    for &p in positions {
+        score += 16;
+        if is_word_start(text, p) {
+            score += 10;
+        }
+        match prev {
+            Some(q) if p == q + 1 => score += 12,
+            Some(q) => score -= (p - q - 1).min(8) as i64,
+            None => score -= p.min(10) as i64,
+        }
+        prev = Some(p);
+    }
+    // Prefer shorter candidates when everything else is equal.
+    score - (text.len() as i64 / 8)
+}
+
+fn is_word_start(text: &[char], i: usize) -> bool {
+    if i == 0 {
+        return true;
+    }
+    let prev = text[i - 1];
+    let cur = text[i];
+    matches!(prev, '_' | '-' | '.' | ' ' | '/' | '\\')
+        || (prev.is_lowercase() && cur.is_uppercase())
+        || (!prev.is_ascii_digit() && cur.is_ascii_digit())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Matcher, fuzzy_match};
+
+    #[test]
+    fn test_fuzzy_match() {
+        let m = fuzzy_match("mrs", "main.rs").unwrap();
+        assert_eq!(m.positions, vec![0, 5, 6]);
+        assert!(fuzzy_match("xyz", "main.rs").is_none());
+        assert!(fuzzy_match("MR", "main.rs").is_none(), "uppercase query is case-sensitive");
+
+        // Consecutive / word-start matches beat scattered ones.
+        let tight = fuzzy_match("walk", "walk.rs").unwrap();
+        let loose = fuzzy_match("walk", "w_a_l_k.rs").unwrap();
+        assert!(tight.score > loose.score);
+
+        // The best alignment is chosen, not the first.
+        let m = fuzzy_match("rs", "render_rs.rs").unwrap();
+        assert_eq!(m.positions, vec![7, 8]);
+    }
+
+    #[test]
+    fn test_regex_matcher() {
+        let re = Matcher::new(r"_test\.rs$", true).unwrap();
+        let m = re.find("walk_test.rs").unwrap();
+        assert_eq!(m.positions, (4..12).collect::<Vec<_>>());
+        assert!(re.find("walk.rs").is_none());
+        // Positions are char indices, not bytes.
+        let m = Matcher::new("é+", true).unwrap().find("caféé.txt").unwrap();
+        assert_eq!(m.positions, vec![3, 4]);
+        assert!(Matcher::new("(", true).is_err());
+    }
+}
@@ -1,11 +1,14 @@
 //! Color and icon resolution, theme application, tty detection.
 
 use std::io::IsTerminal;
+use std::path::Path;
+
 use owo_colors::{OwoColorize, Style};
 
 use crate::cli::{ColorWhen, IconWhen};
 use crate::config::Theme;
 use crate::icons::{self, DIR_ICON, SYMLINK_ICON};
+use crate::lang::{self, Category, Language, Media};
 use crate::tree::NodeKind;
 
 /// Resolved at startup; passed everywhere so we don't re-query `isatty`.
@@ -57,8 +60,6 @@ impl StyleConfig {
 }
 
 /// Color palette derived from a Theme.  Stores pre-built `owo_colors::Style`.
-/// Fields for exec/media/archive/config/doc exist for future per-type coloring.
-#[allow(dead_code)]
 #[derive(Debug, Default, Clone)]
 pub struct Palette {
     pub dir: Style,
@@ -77,7 +78,12 @@ pub struct Palette {
     pub git_ignored: Style,
     pub meta: Style,           // line-counts, sizes
     pub connector: Style,      // tree branches
+    #[allow(dead_code)]
     pub count: Style,          // summary counts
+    pub diff_added: Style,
+    pub diff_removed: Style,
+    pub diff_changed: Style,
+    pub highlight: Style,      // recently changed entries (--watch)
 }
 
 impl Palette {
@@ -106,6 +112,10 @@ impl Palette {
             meta:         parse_style(&colors.meta),
             connector:    parse_style(&colors.connector),
             count:        parse_style(&colors.count),
+            diff_added:   parse_style(&colors.diff_added),
+            diff_removed: parse_style(&colors.diff_removed),
+            diff_changed: parse_style(&colors.diff_changed),
+            highlight:    parse_style(&colors.highlight),
         }
     }
 }
@@ -133,8 +143,39 @@ fn parse_style(s: &str) -> Style {
     }
 }
 
-/// Returns the right icon string for an entry.
-pub fn icon_str(name: &str, kind: NodeKind, style_cfg: &StyleConfig) -> &'static str {
+/// Hex RGB for a theme color name, for formats that can't use ANSI codes.
+/// Returns `None` for `bold` and unknown names.
+pub fn hex_color(name: &str) -> Option<&'static str> {
+    Some(match name {
+        "black"         => "#3b3b3b",
+        "red"           => "#cd3131",
+        "green"         => "#0dbc79",
+        "yellow"        => "#e5e510",
+        "blue"          => "#2472c8",
+        "magenta"       => "#bc3fbc",
+        "cyan"          => "#11a8cd",
+        "white"         => "#e5e5e5",
+        "bright_black" | "dark_gray" => "#8a8a8a",
+        "bright_red"    => "#f14c4c",
+        "bright_green"  => "#23d18b",
+        "bright_yellow" => "#f5f543",
+        "bright_blue"   => "#3b8eea",
+        "bright_magenta"=> "#d670d6",
+        "bright_cyan"   => "#29b8db",
+        "bright_white"  => "#ffffff",
+        _               => return None,
+    })
+}
+
+/// Returns the right icon string for an entry.  Files get the icon of their
+/// language as detected by the walker (`Node::lang`), so a `#!/usr/bin/env
+/// python3` script without an extension still shows the Python icon.
+pub fn icon_str(
+    name: &str,
+    lang: Option<&'static Language>,
+    kind: NodeKind,
+    style_cfg: &StyleConfig,
+) -> &'static str {
     if !style_cfg.use_icons {
         return "";
     }
@@ -147,20 +188,84 @@ pub fn icon_str(name: &str, kind: NodeKind, style_cfg: &StyleConfig) -> &'static
         NodeKind::Symlink => SYMLINK_ICON,
         NodeKind::File => {
             let ext = name.rfind('.').map(|i| &name[i + 1..]).unwrap_or("").to_lowercase();
-            icons::icon_for_ext(&ext)
+            match lang {
+                Some(l) if !l.extensions.contains(&ext.as_str()) => {
+                    icons::icon_for_ext(l.extensions.first().copied().unwrap_or(&ext))
+                }
+                _ => icons::icon_for_ext(&ext),
+            }
         }
     }
 }
 
-/// Return colored string for a name using the palette based on kind.
-pub fn paint_name(s: &str, kind: NodeKind, palette: &Palette) -> String {
+/// Base style for an entry's name: directories and symlinks by kind, files
+/// by executable bit, media type, or the category of their `lang` (data and
+/// config formats, docs).
+pub fn name_style(
+    kind: NodeKind,
+    path: &Path,
+    lang: Option<&'static Language>,
+    is_exec: bool,
+    palette: &Palette,
+) -> Style {
     match kind {
-        NodeKind::Dir => format!("{}", s.style(palette.dir)),
-        NodeKind::Symlink => format!("{}", s.style(palette.symlink)),
-        NodeKind::File => format!("{}", s.style(palette.file)),
+        NodeKind::Dir => palette.dir,
+        NodeKind::Symlink => palette.symlink,
+        NodeKind::File if is_exec => palette.exec,
+        NodeKind::File => {
+            let ext = path
+                .extension()
+                .and_then(|e| e.to_str())
+                .unwrap_or("")
+                .to_lowercase();
+            match lang::media_kind(&ext) {
+                Some(Media::Image) => return palette.image,
+                Some(Media::Audio) => return palette.audio,
+                Some(Media::Video) => return palette.video,
+                Some(Media::Archive) => return palette.archive,
+                Some(Media::Binary) => return palette.file,
+                None => {}
+            }
+            match lang.map(|l| l.category) {
+                Some(Category::Data) => palette.config,
+                Some(Category::Prose) => palette.doc,
+                _ => palette.file,
+            }
+        }
     }
 }
 
+/// Return `s` painted in `base` (see `name_style`).  Characters at the
+/// `matched` char indices (from `--find`) are painted with the highlight
+/// style instead.
+pub fn paint_name(s: &str, base: Style, matched: &[usize], palette: &Palette) -> String {
+    if matched.is_empty() {
+        return format!("{}", s.style(base));
+    }
+    // Paint runs of matched / unmatched characters in one go.
+    let mut out = String::new();
+    let mut run = String::new();
+    let mut run_matched = false;
+    for (i, c) in s.chars().enumerate() {
+        let is_match = matched.contains(&i);
+        if is_match != run_matched && !run.is_empty() {
+            let style = if run_matched { palette.highlight } else { base };
+            out.push_str(&format!("{}", run.style(style)));
+            run.clear();
+        }
+        run_matched = is_match;
+        run.push(c);
+    }
+    let style = if run_matched { palette.highlight } else { base };
+    out.push_str(&format!("{}", run.style(style)));
+    out
+}
+
+/// Paint text with the highlight style.
+pub fn paint_highlight(s: &str, palette: &Palette) -> String {
+    format!("{}", s.style(palette.highlight))
+}
+
 /// Paint a connector segment.
 pub fn paint_connector(s: &str, palette: &Palette) -> String {
     format!("{}", s.style(palette.connector))
@@ -171,24 +276,67 @@ pub fn paint_meta(s: &str, palette: &Palette) -> String {
     format!("{}", s.style(palette.meta))
 }
 
-/// Map a git status byte to a colored glyph string.
-pub fn git_status_glyph(xy: [u8; 2], palette: &Palette) -> String {
-    // Porcelain v1: XY where X=index, Y=worktree
-    let index = xy[0];
-    let work = xy[1];
-    if index == b'?' && work == b'?' {
-        return format!("{}", "?".style(palette.git_untracked));
-    }
-    if index == b'!' && work == b'!' {
-        return format!("{}", "!".style(palette.git_ignored));
+/// Coarse git state of an entry, derived from its porcelain XY bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GitState {
+    Untracked,
+    Ignored,
+    Staged,
+    Modified,
+}
+
+impl GitState {
+    /// Porcelain v1: XY where X=index, Y=worktree.  `None` means clean.
+    pub fn from_xy(xy: [u8; 2]) -> Option<Self> {
+        let index = xy[0];
+        let work = xy[1];
+        if index == b'?' && work == b'?' {
+            return Some(Self::Untracked);
+        }
+        if index == b'!' && work == b'!' {
+            return Some(Self::Ignored);
+        }
+        // Staged changes
+        if matches!(index, b'A' | b'M' | b'R' | b'C' | b'D') {
+            return Some(Self::Staged);
+        }
+        // Worktree modifications
+        if matches!(work, b'M' | b'D') {
+            return Some(Self::Modified);
+        }
+        None
     }
-    // Staged changes
-    if matches!(index, b'A' | b'M' | b'R' | b'C' | b'D') {
-        return format!("{}", "●".style(palette.git_staged));
+
+    pub fn glyph(self) -> &'static str {
+        match self {
+            Self::Untracked => "?",
+            Self::Ignored => "!",
+            Self::Staged => "●",
+            Self::Modified => "✚",
+        }
     }
-    // Worktree modifications
-    if matches!(work, b'M' | b'D') {
-        return format!("{}", "✚".style(palette.git_modified));
+
+    /// Lowercase name, used as a CSS class / label in non-terminal formats.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Untracked => "untracked",
+            Self::Ignored => "ignored",
+            Self::Staged => "staged",
+            Self::Modified => "modified",
+        }
     }
-    String::new()
+}
+
+/// Map a git status byte to a colored glyph string.
+pub fn git_status_glyph(xy: [u8; 2], palette: &Palette) -> String {
+    let Some(state) = GitState::from_xy(xy) else {
+        return String::new();
+    };
+    let style = match state {
+        GitState::Untracked => palette.git_untracked,
+        GitState::Ignored => palette.git_ignored,
+        GitState::Staged => palette.git_staged,
+        GitState::Modified => palette.git_modified,
+    };
+    format!("{}", state.glyph().style(style))
 }
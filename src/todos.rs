@@ -0,0 +1,173 @@
+//! `--todos`: `TODO`/`FIXME`/`HACK`/`XXX` (and configured) markers in
+//! comments, for a per-file count in the tree and a list after it.  Only
+//! comment text counts, as split off by the `--analyze` lexer, so a
+//! `"TODO"` string literal or a `todo!()` call is not a marker.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+
+use crate::git;
+use crate::lang::Language;
+use crate::lexer::{self, Syntax};
+
+pub const DEFAULT_MARKERS: &[&str] = &["TODO", "FIXME", "HACK", "XXX"];
+
+/// One marker found in a comment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Todo {
+    pub path: PathBuf,
+    pub line: usize,
+    pub marker: String,
+    /// The rest of the comment line.
+    pub text: String,
+    /// Who last changed the line, from `git blame` (with `--git`).
+    pub author: Option<String>,
+}
+
+pub struct TodoScanner {
+    /// Built-in markers, then configured ones, in list order.
+    markers: Vec<String>,
+    re: Regex,
+}
+
+impl TodoScanner {
+    /// Scanner for the built-in markers plus `extra` ones (matched literally
+    /// and case-sensitively, as whole words).
+    pub fn new(extra: &[String]) -> Result<Self, String> {
+        let mut markers: Vec<String> = DEFAULT_MARKERS.iter().map(|m| m.to_string()).collect();
+        for m in extra {
+            let m = m.trim();
+            if m.is_empty() {
+                return Err("empty marker in [todos] markers".into());
+            }
+            if !markers.iter().any(|k| k == m) {
+                markers.push(m.to_string());
+            }
+        }
+        let word = |c: Option<char>| c.is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let alternatives: Vec<String> = markers
+            .iter()
+            .map(|m| {
+                format!(
+                    "{}{}{}",
+                    if word(m.chars().next()) { r"\b" } else { "" },
+                    regex::escape(m),
+                    if word(m.chars().last()) { r"\b" } else { "" },
+                )
+            })
+            .collect();
+        let re = Regex::new(&format!("({})[:\\s-]*(.*)", alternatives.join("|")))
+            .map_err(|e| format!("invalid [todos] marker: {}", e))?;
+        Ok(Self { markers, re })
+    }
+
+    /// Markers in comments of `content` (written in `language`), one per line
+    /// at most.
+    pub fn scan(&self, path: &Path, language: Option<&'static Language>, content: &str) -> Vec<Todo> {
+        let syntax = Syntax::for_language(language);
+        let comments = lexer::scan(content, &syntax).comments;
+        comments
+            .lines()
+            .enumerate()
+            .filter_map(|(i, text)| {
+                let caps = self.re.captures(text)?;
+                Some(Todo {
+                    path: path.to_path_buf(),
+                    line: i + 1,
+                    marker: caps[1].to_string(),
+                    text: caps[2].trim_end().trim_end_matches("*/").trim_end().to_string(),
+                    author: None,
+                })
+            })
+            .collect()
+    }
+
+    /// The list printed after the tree: one group per marker, in marker
+    /// order, each entry `path:line  text  (author)`.
+    pub fn format_list(&self, todos: &[Todo], base: &Path) -> String {
+        let location = |t: &Todo| {
+            format!("{}:{}", t.path.strip_prefix(base).unwrap_or(&t.path).display(), t.line)
+        };
+        let width = todos.iter().map(|t| location(t).chars().count()).max().unwrap_or(0);
+        let mut out = String::new();
+        for marker in &self.markers {
+            let group: Vec<&Todo> = todos.iter().filter(|t| &t.marker == marker).collect();
+            if group.is_empty() {
+                continue;
+            }
+            out.push_str(&format!("{} ({})\n", marker, group.len()));
+            for t in group {
+                out.push_str(&format!("  {:<w$}  {}", location(t), t.text, w = width));
+                if let Some(author) = &t.author {
+                    out.push_str(&format!("  ({})", author));
+                }
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+/// Fill in `author` from `git blame`, one run per file.  Files outside a
+/// repository, or not committed, are left as they are.
+pub fn add_authors(todos: &mut [Todo]) {
+    let mut by_file: HashMap<PathBuf, Vec<usize>> = HashMap::new();
+    for t in todos.iter() {
+        by_file.entry(t.path.clone()).or_default().push(t.line);
+    }
+    for (path, lines) in by_file {
+        let Some(authors) = git::blame_authors(&path, &lines) else {
+            continue;
+        };
+        for t in todos.iter_mut().filter(|t| t.path == path) {
+            t.author = authors.get(&t.line).cloned();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use super::TodoScanner;
+    use crate::lang;
+
+    #[test]
+    fn test_scan() {
+        let scanner = TodoScanner::new(&["SAFETY".into()]).unwrap();
+        let src = concat!(
+            "// TODO: handle errors\n",
+            "let s = \"TODO not a comment\";\n",
+            "todo!(); /* FIXME - leaks */\n",
+            "/// XXX doc comment\n",
+            "// TODOS aren't markers\n",
+            "unsafe { x } // SAFETY: checked above\n",
+        );
+        let path = Path::new("src/lib.rs");
+        let found = scanner.scan(path, lang::detect(path, None), src);
+        let summary: Vec<(usize, &str, &str)> = found
+            .iter()
+            .map(|t| (t.line, t.marker.as_str(), t.text.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (1, "TODO", "handle errors"),
+                (3, "FIXME", "leaks"),
+                (4, "XXX", "doc comment"),
+                (6, "SAFETY", "checked above"),
+            ]
+        );
+        assert_eq!(
+            scanner.format_list(&found, Path::new("src")),
+            "TODO (1)\n  lib.rs:1  handle errors\nFIXME (1)\n  lib.rs:3  leaks\n\
+             XXX (1)\n  lib.rs:4  doc comment\nSAFETY (1)\n  lib.rs:6  checked above\n"
+        );
+
+        let py = scanner.scan(Path::new("a.py"), lang::detect(Path::new("a.py"), None), "def f():\n    \"\"\"HACK: docstring\"\"\"\n    x = '# TODO'\n");
+        assert_eq!(py.len(), 1);
+        assert_eq!((py[0].line, py[0].marker.as_str()), (2, "HACK"));
+    }
+}
@@ -1,6 +1,8 @@
 //! In-memory tree model and tree-shape rendering.
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+use crate::lang::Language;
 
 /// Broad kind of a filesystem entry.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -10,6 +12,17 @@ pub enum NodeKind {
     Symlink,
 }
 
+impl NodeKind {
+    /// Name used for this kind in machine-readable output.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NodeKind::Dir => "directory",
+            NodeKind::File => "file",
+            NodeKind::Symlink => "symlink",
+        }
+    }
+}
+
 /// A single node in the directory tree.
 #[derive(Debug)]
 pub struct Node {
@@ -23,10 +36,22 @@ pub struct Node {
     /// Modification time as Unix seconds (0 if unavailable).
     pub mtime: i64,
     /// Whether the file is executable (Unix only; always false on Windows).
-    #[allow(dead_code)]
     pub is_exec: bool,
+    /// Unix permission bits (0 if unavailable, e.g. on Windows).
+    pub mode: u32,
+    /// Line count, filled in by the walker when `--lines` is on.
+    pub lines: Option<usize>,
+    /// Content digest as `"<algo>:<hex>"`, filled in with `--hash`.
+    pub hash: Option<String>,
+    /// Number of lines matching `--grep`, filled in for matching files.
+    pub matches: Option<usize>,
+    /// Content sniffed as binary (only checked when contents are needed).
+    pub binary: bool,
     /// Git status bytes [index, worktree] — 0x20 (' ') means clean.
     pub git_xy: Option<[u8; 2]>,
+    /// Language of a file, detected once by the walker so rendering and
+    /// filtering needn't read the file again.
+    pub lang: Option<&'static Language>,
 }
 
 impl Node {
@@ -44,6 +69,17 @@ impl Node {
             .unwrap_or("")
     }
 
+    /// The node at `path` in this subtree, if present.
+    pub fn find_mut(&mut self, path: &Path) -> Option<&mut Node> {
+        if self.path == path {
+            return Some(self);
+        }
+        if !path.starts_with(&self.path) {
+            return None;
+        }
+        self.children.iter_mut().find_map(|c| c.find_mut(path))
+    }
+
     /// Total file count under this node (recursively).
     pub fn file_count(&self) -> usize {
         if self.kind != NodeKind::Dir {
@@ -70,6 +106,14 @@ impl Node {
         }
         self.children.iter().map(|c| c.total_size()).sum()
     }
+
+    /// Recursive line total, or `None` when no file below was counted.
+    pub fn total_lines(&self) -> Option<usize> {
+        if self.kind != NodeKind::Dir {
+            return self.lines;
+        }
+        self.children.iter().filter_map(|c| c.total_lines()).reduce(|a, b| a + b)
+    }
 }
 
 /// Human-readable byte size.
@@ -85,3 +129,23 @@ pub fn human_size(bytes: u64) -> String {
         format!("{:.1}G", bytes as f64 / (K * K * K) as f64)
     }
 }
+
+/// A node with the given shape and no other metadata, for tests.
+#[cfg(test)]
+pub fn test_node(path: &str, kind: NodeKind, size: u64, children: Vec<Node>) -> Node {
+    Node {
+        path: path.into(),
+        kind,
+        children,
+        size,
+        mtime: 0,
+        is_exec: false,
+        mode: 0,
+        lines: None,
+        hash: None,
+        matches: None,
+        binary: false,
+        git_xy: None,
+        lang: None,
+    }
+}
@@ -0,0 +1,484 @@
+//! Exact function/method/type/trait/test counts, typed declarations and
+//! function metrics for `--analyze`, from a tree-sitter parse.  Built with
+//! `--features tree-sitter`; files in other languages, or that fail to
+//! parse, fall back to the line heuristics in `analyze` and `complexity`.
+
+use tree_sitter::{Language, Node, Parser};
+
+use crate::analyze::{Declarations, ItemCounts};
+use crate::complexity::FunctionMetrics;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Grammar {
+    Rust,
+    Python,
+    JavaScript,
+    TypeScript,
+    Tsx,
+    Go,
+    C,
+    Cpp,
+    Java,
+}
+
+impl Grammar {
+    /// By `lang::Language::id`.
+    fn for_id(id: &str) -> Option<Self> {
+        Some(match id {
+            "rust" => Grammar::Rust,
+            "python" => Grammar::Python,
+            "javascript" | "jsx" => Grammar::JavaScript,
+            "typescript" => Grammar::TypeScript,
+            "tsx" => Grammar::Tsx,
+            "go" => Grammar::Go,
+            "c" => Grammar::C,
+            "cpp" => Grammar::Cpp,
+            "java" => Grammar::Java,
+            _ => return None,
+        })
+    }
+
+    fn language(self) -> Language {
+        match self {
+            Grammar::Rust => tree_sitter_rust::LANGUAGE.into(),
+            Grammar::Python => tree_sitter_python::LANGUAGE.into(),
+            Grammar::JavaScript => tree_sitter_javascript::LANGUAGE.into(),
+            Grammar::TypeScript => tree_sitter_typescript::LANGUAGE_TYPESCRIPT.into(),
+            Grammar::Tsx => tree_sitter_typescript::LANGUAGE_TSX.into(),
+            Grammar::Go => tree_sitter_go::LANGUAGE.into(),
+            Grammar::C => tree_sitter_c::LANGUAGE.into(),
+            Grammar::Cpp => tree_sitter_cpp::LANGUAGE.into(),
+            Grammar::Java => tree_sitter_java::LANGUAGE.into(),
+        }
+    }
+}
+
+/// What a syntax node declares.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Item {
+    Function,
+    Method,
+    Type,
+    Trait,
+}
+
+/// The innermost declaration enclosing a node, which decides whether a
+/// function is a method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Scope {
+    Top,
+    Type,
+    Function,
+}
+
+/// Item counts, declarations and per-function metrics for `source` in the
+/// language `lang_id`, or `None` if there is no grammar for it or the parse
+/// has errors.  Lambdas and closures that count as functions are measured
+/// on their own, not as part of the function around them.
+pub fn parse(
+    lang_id: &str,
+    source: &str,
+) -> Option<(ItemCounts, Declarations, Vec<FunctionMetrics>)> {
+    let grammar = Grammar::for_id(lang_id)?;
+    let mut parser = Parser::new();
+    parser.set_language(&grammar.language()).ok()?;
+    let tree = parser.parse(source, None)?;
+    if tree.root_node().has_error() {
+        return None;
+    }
+    let mut found = Found::default();
+    walk(tree.root_node(), source.as_bytes(), grammar, Scope::Top, &mut found);
+    Some((found.items, found.declarations, found.functions))
+}
+
+/// What `walk` collects.
+#[derive(Default)]
+struct Found {
+    items: ItemCounts,
+    declarations: Declarations,
+    functions: Vec<FunctionMetrics>,
+}
+
+fn walk(node: Node, src: &[u8], grammar: Grammar, scope: Scope, found: &mut Found) {
+    if let Some(ty) = declared_type(node) {
+        count_declaration(text(ty, src), &mut found.declarations);
+    }
+    let (item, inner) = classify(node, src, grammar, scope);
+    let counts = &mut found.items;
+    match item {
+        Some(Item::Function) => counts.functions += 1,
+        Some(Item::Method) => counts.methods += 1,
+        Some(Item::Type) => counts.types += 1,
+        Some(Item::Trait) => counts.traits += 1,
+        None => {}
+    }
+    if matches!(item, Some(Item::Function | Item::Method)) {
+        if is_test(node, src, grammar) {
+            counts.tests += 1;
+        }
+        let (decisions, nesting) = measure_body(node, src, grammar, 0);
+        found.functions.push(FunctionMetrics {
+            name: function_name(node, src),
+            line: node.start_position().row + 1,
+            complexity: 1 + decisions,
+            nesting,
+            length: node.end_position().row - node.start_position().row + 1,
+        });
+    }
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        walk(child, src, grammar, inner, found);
+    }
+}
+
+/// The type of a variable, constant, field or parameter declaration.
+/// Node kinds are unique enough across the grammars to match them all at
+/// once; ones without a `type` field (`x := 1`, untyped JS) yield nothing.
+fn declared_type(node: Node) -> Option<Node> {
+    match node.kind() {
+        // Rust
+        "let_declaration" | "parameter" | "const_item" | "static_item"
+        // C/C++, and Go's parameters and fields
+        | "declaration" | "parameter_declaration" | "field_declaration"
+        // Java
+        | "local_variable_declaration" | "formal_parameter"
+        // Go
+        | "var_spec" | "const_spec"
+        // Python
+        | "typed_parameter" | "typed_default_parameter" | "assignment"
+        // TypeScript
+        | "required_parameter" | "optional_parameter" | "variable_declarator"
+        | "public_field_definition" | "property_signature" => node.child_by_field_name("type"),
+        _ => None,
+    }
+}
+
+/// Count a declaration of type `ty` if it is an integer, float, string or
+/// boolean.  Qualifiers and paths are dropped: `&'a str`, `unsigned int`
+/// and `std::string` count by their last word.
+fn count_declaration(ty: &str, decls: &mut Declarations) {
+    let name = ty
+        .rsplit(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .find(|w| !w.is_empty())
+        .unwrap_or("");
+    let slot = match name {
+        "i8" | "i16" | "i32" | "i64" | "i128" | "isize" | "u8" | "u16" | "u32" | "u64" | "u128"
+        | "usize" | "int" | "long" | "short" | "byte" | "int8" | "int16" | "int32" | "int64"
+        | "uint" | "uint8" | "uint16" | "uint32" | "uint64" | "uintptr" | "size_t" | "bigint" => {
+            &mut decls.int
+        }
+        "f32" | "f64" | "float" | "double" | "float32" | "float64" | "number" => &mut decls.float,
+        "String" | "str" | "string" => &mut decls.string,
+        "bool" | "boolean" | "_Bool" => &mut decls.bool,
+        _ => return,
+    };
+    *slot += 1;
+}
+
+fn is_function(node: Node, src: &[u8], grammar: Grammar) -> bool {
+    matches!(classify(node, src, grammar, Scope::Top).0, Some(Item::Function | Item::Method))
+}
+
+/// Decision points below `node` and the deepest control-block nesting,
+/// not looking into nested functions.
+fn measure_body(node: Node, src: &[u8], grammar: Grammar, depth: usize) -> (usize, usize) {
+    let (mut decisions, mut nesting) = (0, depth);
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if is_function(child, src, grammar) {
+            continue;
+        }
+        if is_decision(child, src) {
+            decisions += 1;
+        }
+        let child_depth = if is_control(child) { depth + 1 } else { depth };
+        let (d, n) = measure_body(child, src, grammar, child_depth);
+        decisions += d;
+        nesting = nesting.max(n);
+    }
+    (decisions, nesting)
+}
+
+fn is_decision(node: Node, src: &[u8]) -> bool {
+    match node.kind() {
+        "if_expression" | "if_statement" | "elif_clause" | "conditional_expression"
+        | "ternary_expression" | "for_expression" | "while_expression" | "for_statement"
+        | "for_in_statement" | "while_statement" | "do_statement" | "enhanced_for_statement"
+        | "for_range_loop" | "catch_clause" | "except_clause" | "case_clause" | "switch_case"
+        | "expression_case" | "type_case" | "communication_case" => true,
+        "boolean_operator" => true,
+        "binary_expression" => node
+            .child_by_field_name("operator")
+            .is_some_and(|op| matches!(op.kind(), "&&" | "||")),
+        // Arms and labels, except the catch-all.
+        "match_arm" => node.child_by_field_name("pattern").is_none_or(|p| text(p, src) != "_"),
+        "case_statement" | "switch_label" => !text(node, src).starts_with("default"),
+        _ => false,
+    }
+}
+
+/// Blocks that deepen nesting.  An `else if` continues its `if` rather
+/// than nesting inside it.
+fn is_control(node: Node) -> bool {
+    let kind = node.kind();
+    let control = matches!(
+        kind,
+        "if_expression" | "if_statement" | "for_expression" | "while_expression"
+            | "loop_expression" | "match_expression" | "for_statement" | "for_in_statement"
+            | "while_statement" | "do_statement" | "enhanced_for_statement" | "for_range_loop"
+            | "switch_statement" | "expression_switch_statement" | "type_switch_statement"
+            | "select_statement" | "match_statement" | "try_statement" | "with_statement"
+    );
+    let else_if = kind.starts_with("if_")
+        && node.parent().is_some_and(|p| {
+            p.kind() == "else_clause"
+                || (p.kind() == kind && p.child_by_field_name("alternative") == Some(node))
+        });
+    control && !else_if
+}
+
+/// The declared name, the C declarator's name, or the variable an
+/// anonymous function is assigned to.
+fn function_name(node: Node, src: &[u8]) -> String {
+    if let Some(name) = node.child_by_field_name("name") {
+        return text(name, src).to_string();
+    }
+    if let Some(mut d) = node.child_by_field_name("declarator") {
+        while let Some(inner) = d.child_by_field_name("declarator") {
+            d = inner;
+        }
+        return text(d, src).to_string();
+    }
+    node.parent()
+        .filter(|p| matches!(p.kind(), "variable_declarator" | "assignment_expression" | "pair"))
+        .and_then(|p| p.child_by_field_name("name").or_else(|| p.child_by_field_name("left")).or_else(|| p.child_by_field_name("key")))
+        .map_or_else(|| "<anonymous>".to_string(), |n| text(n, src).to_string())
+}
+
+/// The item `node` declares, and the scope its children are in.
+fn classify(node: Node, src: &[u8], grammar: Grammar, scope: Scope) -> (Option<Item>, Scope) {
+    let function = |in_type: bool| {
+        let item = if in_type { Item::Method } else { Item::Function };
+        (Some(item), Scope::Function)
+    };
+    // Keywords are anonymous nodes that can share a kind with a named one
+    // (`class` the keyword vs. `class` the expression).
+    if !node.is_named() {
+        return (None, scope);
+    }
+    let kind = node.kind();
+    match grammar {
+        Grammar::Rust => match kind {
+            "function_item" | "function_signature_item" => function(scope == Scope::Type),
+            "impl_item" => (None, Scope::Type),
+            "trait_item" => (Some(Item::Trait), Scope::Type),
+            "struct_item" | "enum_item" | "union_item" | "type_item" => (Some(Item::Type), scope),
+            _ => (None, scope),
+        },
+        Grammar::Python => match kind {
+            "function_definition" => function(scope == Scope::Type),
+            "class_definition" => {
+                let bases = node
+                    .child_by_field_name("superclasses")
+                    .map(|b| text(b, src))
+                    .unwrap_or_default();
+                let is_protocol = bases.contains("Protocol") || bases.contains("ABC");
+                (Some(if is_protocol { Item::Trait } else { Item::Type }), Scope::Type)
+            }
+            _ => (None, scope),
+        },
+        Grammar::JavaScript | Grammar::TypeScript | Grammar::Tsx => match kind {
+            "function_declaration" | "generator_function_declaration" | "function_expression"
+            | "generator_function" | "arrow_function" => function(false),
+            "method_definition" | "method_signature" | "abstract_method_signature" => {
+                function(true)
+            }
+            "class_declaration" | "class" | "abstract_class_declaration" => {
+                (Some(Item::Type), Scope::Type)
+            }
+            "enum_declaration" | "type_alias_declaration" => (Some(Item::Type), scope),
+            "interface_declaration" => (Some(Item::Trait), Scope::Type),
+            _ => (None, scope),
+        },
+        Grammar::Go => match kind {
+            "function_declaration" | "func_literal" => function(false),
+            "method_declaration" | "method_elem" => function(true),
+            "type_spec" | "type_alias" => {
+                let is_interface = node
+                    .child_by_field_name("type")
+                    .is_some_and(|t| t.kind() == "interface_type");
+                (Some(if is_interface { Item::Trait } else { Item::Type }), scope)
+            }
+            _ => (None, scope),
+        },
+        Grammar::C | Grammar::Cpp => match kind {
+            "function_definition" => {
+                // `void Widget::draw() { … }` defines a method out of line.
+                let qualified = node
+                    .child_by_field_name("declarator")
+                    .is_some_and(|d| text(d, src).contains("::"));
+                function(scope == Scope::Type || qualified)
+            }
+            "struct_specifier" | "union_specifier" | "enum_specifier" | "class_specifier"
+                if node.child_by_field_name("body").is_some() =>
+            {
+                (Some(Item::Type), Scope::Type)
+            }
+            "alias_declaration" => (Some(Item::Type), scope),
+            "concept_definition" => (Some(Item::Trait), scope),
+            _ => (None, scope),
+        },
+        Grammar::Java => match kind {
+            "method_declaration" | "constructor_declaration" => function(true),
+            "lambda_expression" => function(false),
+            "class_declaration" | "enum_declaration" | "record_declaration" => {
+                (Some(Item::Type), Scope::Type)
+            }
+            "interface_declaration" | "annotation_type_declaration" => {
+                (Some(Item::Trait), Scope::Type)
+            }
+            _ => (None, scope),
+        },
+    }
+}
+
+/// Test functions: `#[test]` (and `#[tokio::test]` …) in Rust, `test_*` in
+/// Python, `TestXxx`/`BenchmarkXxx`/`FuzzXxx` in Go, `@Test` in Java, gtest
+/// `TEST(…)` macros in C++, and the callback passed to `it(…)`/`test(…)`
+/// in JS/TS.
+fn is_test(node: Node, src: &[u8], grammar: Grammar) -> bool {
+    let name = || node.child_by_field_name("name").map(|n| text(n, src)).unwrap_or_default();
+    match grammar {
+        Grammar::Rust => {
+            let mut prev = node.prev_named_sibling();
+            while let Some(p) = prev {
+                match p.kind() {
+                    "attribute_item" if text(p, src).contains("test") => return true,
+                    "attribute_item" | "line_comment" | "block_comment" => prev = p.prev_named_sibling(),
+                    _ => break,
+                }
+            }
+            false
+        }
+        Grammar::Python => name().starts_with("test"),
+        Grammar::Go => ["Test", "Benchmark", "Fuzz"].iter().any(|p| name().starts_with(p)),
+        Grammar::Java => node
+            .child_by_field_name("modifiers")
+            .or_else(|| node.child(0).filter(|c| c.kind() == "modifiers"))
+            .is_some_and(|m| text(m, src).contains("@Test")),
+        Grammar::Cpp => node.child_by_field_name("declarator").is_some_and(|d| {
+            let t = text(d, src);
+            ["TEST(", "TEST_F(", "TEST_P(", "TEST_CASE("].iter().any(|m| t.starts_with(m))
+        }),
+        Grammar::JavaScript | Grammar::TypeScript | Grammar::Tsx => node
+            .parent()
+            .filter(|p| p.kind() == "arguments")
+            .and_then(|p| p.parent())
+            .and_then(|call| call.child_by_field_name("function"))
+            .is_some_and(|f| {
+                let callee = text(f, src);
+                let head = callee.split('.').next().unwrap_or("");
+                matches!(head, "it" | "test")
+            }),
+        Grammar::C => false,
+    }
+}
+
+fn text<'a>(node: Node, src: &'a [u8]) -> &'a str {
+    node.utf8_text(src).unwrap_or("")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse;
+    use crate::analyze::{Declarations, ItemCounts};
+
+    fn count_items(lang_id: &str, source: &str) -> Option<ItemCounts> {
+        parse(lang_id, source).map(|(counts, _, _)| counts)
+    }
+
+    fn count_declarations(lang_id: &str, source: &str) -> Declarations {
+        parse(lang_id, source).map(|(_, decls, _)| decls).unwrap()
+    }
+
+    fn counts(functions: usize, methods: usize, types: usize, traits: usize, tests: usize) -> ItemCounts {
+        ItemCounts { functions, methods, types, traits, tests }
+    }
+
+    #[test]
+    fn test_count_items() {
+        let rust = r#"
+            pub(crate) fn helper() -> i32 { 1 }
+            struct Point { x: i32 }
+            enum Kind { A, B }
+            trait Shape { fn area(&self) -> f64; }
+            impl Shape for Point {
+                fn area(&self) -> f64 { fn inner() {} 0.0 }
+            }
+            // fn not_code() {}
+            #[cfg(test)]
+            mod tests {
+                #[test]
+                fn it_works() { let s = "fn fake() {}"; }
+            }
+        "#;
+        assert_eq!(count_items("rust", rust), Some(counts(3, 2, 2, 1, 1)));
+
+        let python = "class A:\n    def m(self): pass\n\nclass P(Protocol):\n    def f(self): ...\n\ndef test_a():\n    def inner(): pass\n";
+        assert_eq!(count_items("python", python), Some(counts(2, 2, 1, 1, 1)));
+
+        let ts = "interface I { m(): void }\nclass C implements I { m() {} }\nconst f = (x: number) => x;\ntest('adds', () => {});\nfunction g() {}\n";
+        assert_eq!(count_items("typescript", ts), Some(counts(3, 2, 1, 1, 1)));
+
+        let go = "package p\ntype S struct{}\ntype R interface{ Read() }\nfunc (s S) M() {}\nfunc F() {}\nfunc TestF(t *testing.T) {}\n";
+        assert_eq!(count_items("go", go), Some(counts(2, 2, 1, 1, 1)));
+
+        let c = "struct point { int x; };\n/* int f() {} */\nstatic int add(int a, int b) { return a + b; }\n";
+        assert_eq!(count_items("c", c), Some(counts(1, 0, 1, 0, 0)));
+
+        let cpp = "class W { public: void draw() {} };\nvoid W2::draw() {}\nint main() { return 0; }\n";
+        assert_eq!(count_items("cpp", cpp), Some(counts(1, 2, 1, 0, 0)));
+
+        let java = "interface I {}\nclass T { T() {} @Test void t() {} void m() { Runnable r = () -> {}; } }\n";
+        assert_eq!(count_items("java", java), Some(counts(1, 3, 1, 1, 1)));
+
+        assert_eq!(count_items("ruby", "def f; end"), None);
+        assert_eq!(count_items("rust", "fn broken( {"), None);
+    }
+
+    #[test]
+    fn test_function_metrics() {
+        let rust = "fn f(x: u8, v: &[u8]) -> u8 {\n    if x > 1 && v.is_empty() {\n        for b in v {\n            match b { 0 => {}, _ => {} }\n        }\n    } else if x == 0 {\n        v.iter().for_each(|b| if *b > 0 {});\n    }\n    0\n}\n";
+        let (_, _, functions) = parse("rust", rust).unwrap();
+        let f = &functions[0];
+        assert_eq!((f.name.as_str(), f.line, f.complexity, f.nesting, f.length), ("f", 1, 7, 3, 10));
+
+        let ts = "const g = (a: number) => a > 0 ? 1 : 0;\nclass C { m() { try { g(1) } catch (e) {} } }\n";
+        let (_, _, functions) = parse("typescript", ts).unwrap();
+        let summary: Vec<_> = functions.iter().map(|f| (f.name.as_str(), f.complexity, f.nesting)).collect();
+        assert_eq!(summary, [("g", 2, 0), ("m", 2, 1)]);
+    }
+
+    #[test]
+    fn test_count_declarations() {
+        let decls = |int, float, string, bool| Declarations { int, float, string, bool };
+
+        let rust = "// let a: i32 = 0;\nfn f(n: usize, s: &'a str) -> bool {\n    let x: f64 = 0.0;\n    println!(\"{}\", n);\n    true\n}\nstruct P { name: String, ok: bool }\n";
+        assert_eq!(count_declarations("rust", rust), decls(1, 1, 2, 1));
+
+        let c = "unsigned int n = 0;\nstatic double avg(const float *v, int len) { _Bool done; return 0; }\n";
+        assert_eq!(count_declarations("c", c), decls(2, 1, 0, 1));
+
+        let java = "class A { String s; long n; void m(boolean b) { int printed = 0; } }\n";
+        assert_eq!(count_declarations("java", java), decls(2, 0, 1, 1));
+
+        let go = "package p\nvar n int64\nfunc f(s string, ok bool) { x := 1; print(x) }\n";
+        assert_eq!(count_declarations("go", go), decls(1, 0, 1, 1));
+
+        let python = "def f(n: int, name: str = 'x') -> None:\n    ratio: float = 0.5\n    print(n)\n";
+        assert_eq!(count_declarations("python", python), decls(1, 1, 1, 0));
+
+        let ts = "let n: number = 1;\nfunction f(s: string, ok?: boolean) {}\ninterface I { id: bigint }\n";
+        assert_eq!(count_declarations("typescript", ts), decls(1, 1, 1, 1));
+    }
+}
@@ -0,0 +1,652 @@
+//! `--tui`: full-screen browser over the `Node` tree.
+//!
+//! The UI is drawn on stderr so that stdout stays free for `--print-selected`,
+//! which turns the browser into a picker for shell scripts:
+//!
+//! ```sh
+//! vim "$(ssp -t --print-selected)"
+//! ```
+
+use std::collections::HashSet;
+use std::io::{self, Read as _};
+use std::path::{Path, PathBuf};
+
+use ratatui::backend::CrosstermBackend;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::execute;
+use ratatui::crossterm::terminal::{
+    EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
+};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{Frame, Terminal};
+
+use crate::config::ThemeColors;
+use crate::content::{self, Content};
+use crate::search::fuzzy_match;
+use crate::tree::{Node, NodeKind, human_size};
+
+/// Bytes of a file read for the preview pane.
+const PREVIEW_BYTES: u64 = 256 * 1024;
+
+/// Initial state of the toggles.
+#[derive(Debug, Clone, Copy)]
+pub struct TuiOptions {
+    pub show_hidden: bool,
+    pub respect_gitignore: bool,
+    pub show_sizes: bool,
+    /// Enter on a file picks it and exits.
+    pub picker: bool,
+}
+
+/// What the event loop should do after a key press.
+#[derive(Debug, PartialEq, Eq)]
+enum Action {
+    Continue,
+    /// The hidden / gitignore toggles changed: walk the directory again.
+    Rebuild,
+    Quit,
+}
+
+/// One visible line of the tree pane.
+#[derive(Debug)]
+struct Row {
+    path: PathBuf,
+    name: String,
+    kind: NodeKind,
+    size: u64,
+    files: usize,
+    dirs: usize,
+    depth: usize,
+    has_children: bool,
+    /// Char indices of the name matched by the search query.
+    matched: Vec<usize>,
+}
+
+struct Preview {
+    path: PathBuf,
+    info: String,
+    lines: Vec<String>,
+}
+
+struct App {
+    root: Node,
+    opts: TuiOptions,
+    expanded: HashSet<PathBuf>,
+    rows: Vec<Row>,
+    selected: usize,
+    query: String,
+    searching: bool,
+    chosen: Option<PathBuf>,
+    /// Rows visible in the tree pane, updated on every draw.
+    page: usize,
+    preview: Option<Preview>,
+}
+
+impl App {
+    fn new(root: Node, opts: TuiOptions) -> Self {
+        let mut app = Self {
+            root,
+            opts,
+            expanded: HashSet::new(),
+            rows: Vec::new(),
+            selected: 0,
+            query: String::new(),
+            searching: false,
+            chosen: None,
+            page: 20,
+            preview: None,
+        };
+        app.refresh();
+        app
+    }
+
+    fn set_root(&mut self, root: Node) {
+        self.root = root;
+        self.refresh();
+    }
+
+    /// Recompute the visible rows, keeping the selection on the same path
+    /// when it is still visible.
+    fn refresh(&mut self) {
+        let current = self.rows.get(self.selected).map(|r| r.path.clone());
+        let mut rows = Vec::new();
+        for child in &self.root.children {
+            self.push_rows(child, 0, &mut rows);
+        }
+        self.rows = rows;
+        self.selected = current
+            .and_then(|p| self.rows.iter().position(|r| r.path == p))
+            .unwrap_or(self.selected)
+            .min(self.rows.len().saturating_sub(1));
+        // While searching, jump to the best match instead.
+        if !self.query.is_empty()
+            && let Some(best) = self.best_match()
+            && self.rows[self.selected].matched.is_empty()
+        {
+            self.selected = best;
+        }
+    }
+
+    /// Append `node` (and its visible descendants) to `rows`.  With a search
+    /// query only matches and their ancestors are kept, fully expanded.
+    /// Returns whether anything was added.
+    fn push_rows(&self, node: &Node, depth: usize, rows: &mut Vec<Row>) -> bool {
+        let name = node.name();
+        let matched = if self.query.is_empty() {
+            Some(Vec::new())
+        } else {
+            fuzzy_match(&self.query, name).map(|m| m.positions)
+        };
+        let start = rows.len();
+        rows.push(Row {
+            path: node.path.clone(),
+            name: name.to_string(),
+            kind: node.kind,
+            size: node.total_size(),
+            files: node.file_count(),
+            dirs: node.dir_count(),
+            depth,
+            has_children: !node.children.is_empty(),
+            matched: matched.clone().unwrap_or_default(),
+        });
+
+        let descend = !self.query.is_empty() || self.expanded.contains(&node.path);
+        let mut any_child = false;
+        if descend {
+            for child in &node.children {
+                any_child |= self.push_rows(child, depth + 1, rows);
+            }
+        }
+        if matched.is_none() && !any_child {
+            rows.truncate(start);
+            return false;
+        }
+        true
+    }
+
+    fn best_match(&self) -> Option<usize> {
+        self.rows
+            .iter()
+            .enumerate()
+            .filter_map(|(i, r)| fuzzy_match(&self.query, &r.name).map(|m| (m.score, i)))
+            .max_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)))
+            .map(|(_, i)| i)
+    }
+
+    fn selected_row(&self) -> Option<&Row> {
+        self.rows.get(self.selected)
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        if self.rows.is_empty() {
+            return;
+        }
+        let last = self.rows.len() as isize - 1;
+        self.selected = (self.selected as isize + delta).clamp(0, last) as usize;
+    }
+
+    /// Move to the next (`forward`) or previous row whose name matches.
+    fn jump_match(&mut self, forward: bool) {
+        let n = self.rows.len();
+        for step in 1..n {
+            let i = if forward {
+                (self.selected + step) % n
+            } else {
+                (self.selected + n - step) % n
+            };
+            if !self.rows[i].matched.is_empty() {
+                self.selected = i;
+                return;
+            }
+        }
+    }
+
+    fn expand(&mut self) {
+        if let Some(row) = self.selected_row()
+            && row.kind == NodeKind::Dir
+        {
+            let (path, has_children) = (row.path.clone(), row.has_children);
+            if self.expanded.insert(path) {
+                self.refresh();
+            } else if has_children {
+                self.move_by(1);
+            }
+        }
+    }
+
+    /// Collapse the selected directory, or go to the parent if it is already
+    /// collapsed (or a file).
+    fn collapse(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        let path = row.path.clone();
+        if row.kind == NodeKind::Dir && self.query.is_empty() && self.expanded.remove(&path) {
+            self.refresh();
+            return;
+        }
+        if let Some(parent) = path.parent()
+            && let Some(i) = self.rows.iter().position(|r| r.path == parent)
+        {
+            self.selected = i;
+        }
+    }
+
+    fn toggle(&mut self) {
+        if let Some(row) = self.selected_row()
+            && row.kind == NodeKind::Dir
+        {
+            let path = row.path.clone();
+            if !self.expanded.remove(&path) {
+                self.expanded.insert(path);
+            }
+            self.refresh();
+        }
+    }
+
+    fn choose(&mut self) -> Action {
+        match self.selected_row() {
+            Some(row) => {
+                self.chosen = Some(row.path.clone());
+                Action::Quit
+            }
+            None => Action::Continue,
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) -> Action {
+        if key.modifiers.contains(KeyModifiers::CONTROL) {
+            match key.code {
+                KeyCode::Char('c') => return Action::Quit,
+                KeyCode::Char('d') => self.move_by(self.page as isize / 2),
+                KeyCode::Char('u') => self.move_by(-(self.page as isize) / 2),
+                _ => {}
+            }
+            return Action::Continue;
+        }
+
+        if self.searching {
+            match key.code {
+                KeyCode::Esc => {
+                    self.searching = false;
+                    self.query.clear();
+                    self.refresh();
+                }
+                KeyCode::Enter => self.searching = false,
+                KeyCode::Backspace => {
+                    self.query.pop();
+                    self.refresh();
+                }
+                KeyCode::Up => self.move_by(-1),
+                KeyCode::Down => self.move_by(1),
+                KeyCode::Char(c) => {
+                    self.query.push(c);
+                    self.refresh();
+                }
+                _ => {}
+            }
+            return Action::Continue;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return Action::Quit,
+            KeyCode::Esc if !self.query.is_empty() => {
+                self.query.clear();
+                self.refresh();
+            }
+            KeyCode::Esc => return Action::Quit,
+            KeyCode::Char('j') | KeyCode::Down => self.move_by(1),
+            KeyCode::Char('k') | KeyCode::Up => self.move_by(-1),
+            KeyCode::Char('g') | KeyCode::Home => self.selected = 0,
+            KeyCode::Char('G') | KeyCode::End => self.move_by(isize::MAX / 2),
+            KeyCode::PageDown => self.move_by(self.page as isize),
+            KeyCode::PageUp => self.move_by(-(self.page as isize)),
+            KeyCode::Char('l') | KeyCode::Right => self.expand(),
+            KeyCode::Char('h') | KeyCode::Left => self.collapse(),
+            KeyCode::Char(' ') | KeyCode::Tab => self.toggle(),
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Char('n') => self.jump_match(true),
+            KeyCode::Char('N') => self.jump_match(false),
+            KeyCode::Char('s') => self.opts.show_sizes = !self.opts.show_sizes,
+            KeyCode::Char('.') => {
+                self.opts.show_hidden = !self.opts.show_hidden;
+                return Action::Rebuild;
+            }
+            KeyCode::Char('i') => {
+                self.opts.respect_gitignore = !self.opts.respect_gitignore;
+                return Action::Rebuild;
+            }
+            KeyCode::Char('p') if self.opts.picker => return self.choose(),
+            KeyCode::Enter => match self.selected_row().map(|r| r.kind) {
+                Some(NodeKind::Dir) => self.toggle(),
+                Some(_) if self.opts.picker => return self.choose(),
+                _ => {}
+            },
+            _ => {}
+        }
+        Action::Continue
+    }
+
+    /// Load the preview for the selected row unless it is already cached.
+    fn update_preview(&mut self) {
+        let Some(row) = self.selected_row() else {
+            self.preview = None;
+            return;
+        };
+        if self.preview.as_ref().is_some_and(|p| p.path == row.path) {
+            return;
+        }
+        let preview = match row.kind {
+            NodeKind::Dir => Preview {
+                path: row.path.clone(),
+                info: format!(
+                    "{} files, {} dirs, {}",
+                    row.files,
+                    row.dirs,
+                    human_size(row.size)
+                ),
+                lines: Vec::new(),
+            },
+            _ => file_preview(&row.path, row.size),
+        };
+        self.preview = Some(preview);
+    }
+}
+
+fn file_preview(path: &Path, size: u64) -> Preview {
+    let mut bytes = Vec::new();
+    let read = std::fs::File::open(path).and_then(|f| f.take(PREVIEW_BYTES).read_to_end(&mut bytes));
+    let (info, lines) = match read.map(|_| content::decode(&bytes)) {
+        Err(e) => (format!("cannot read: {}", e), Vec::new()),
+        Ok(Content::Binary) => (format!("binary, {}", human_size(size)), Vec::new()),
+        Ok(Content::Text { text, encoding }) => {
+            let lines: Vec<String> = text.lines().map(|l| l.replace('\t', "    ")).collect();
+            let mut info = if size > PREVIEW_BYTES {
+                format!("{}+ lines", lines.len())
+            } else {
+                format!("{} lines", lines.len())
+            };
+            if encoding.is_notable() {
+                info.push_str(&format!(", {}", encoding.name()));
+            }
+            (format!("{}, {}", info, human_size(size)), lines)
+        }
+    };
+    Preview {
+        path: path.to_path_buf(),
+        info,
+        lines,
+    }
+}
+
+/// Map a theme color name onto the terminal palette.
+fn tui_color(name: &str) -> Color {
+    match name {
+        "black" => Color::Black,
+        "red" => Color::Red,
+        "green" => Color::Green,
+        "yellow" => Color::Yellow,
+        "blue" => Color::Blue,
+        "magenta" => Color::Magenta,
+        "cyan" => Color::Cyan,
+        "white" => Color::Gray,
+        "bright_black" | "dark_gray" => Color::DarkGray,
+        "bright_red" => Color::LightRed,
+        "bright_green" => Color::LightGreen,
+        "bright_yellow" => Color::LightYellow,
+        "bright_blue" => Color::LightBlue,
+        "bright_magenta" => Color::LightMagenta,
+        "bright_cyan" => Color::LightCyan,
+        "bright_white" => Color::White,
+        _ => Color::Reset,
+    }
+}
+
+struct Colors {
+    dir: Style,
+    file: Style,
+    symlink: Style,
+    meta: Style,
+    highlight: Style,
+}
+
+impl Colors {
+    fn new(theme: &ThemeColors, use_color: bool) -> Self {
+        let fg = |name: &str| {
+            if use_color {
+                Style::default().fg(tui_color(name))
+            } else {
+                Style::default()
+            }
+        };
+        Self {
+            dir: fg(&theme.dir).add_modifier(Modifier::BOLD),
+            file: fg(&theme.file),
+            symlink: fg(&theme.symlink),
+            meta: fg(&theme.meta),
+            highlight: fg(&theme.highlight).add_modifier(Modifier::BOLD | Modifier::UNDERLINED),
+        }
+    }
+}
+
+fn draw(frame: &mut Frame, app: &mut App, colors: &Colors) {
+    let outer = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(1), Constraint::Length(1)])
+        .split(frame.area());
+    let panes = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
+        .split(outer[0]);
+
+    app.page = panes[0].height.saturating_sub(2).max(1) as usize;
+    draw_tree(frame, app, colors, panes[0]);
+    app.update_preview();
+    draw_preview(frame, app, colors, panes[1]);
+
+    let toggles = format!(
+        "[.] hidden:{} [i] gitignore:{} [s] sizes:{}",
+        on_off(app.opts.show_hidden),
+        on_off(app.opts.respect_gitignore),
+        on_off(app.opts.show_sizes)
+    );
+    let status = if app.searching {
+        format!("/{}▏  (Enter keep, Esc clear)", app.query)
+    } else if !app.query.is_empty() {
+        format!("filter: {}  (n/N next/prev, Esc clear)  {}", app.query, toggles)
+    } else {
+        let pick = if app.opts.picker { " Enter/p pick" } else { "" };
+        format!("j/k move  h/l fold  / search{}  q quit  {}", pick, toggles)
+    };
+    frame.render_widget(Paragraph::new(status).style(colors.meta), outer[1]);
+}
+
+fn on_off(b: bool) -> &'static str {
+    if b { "on" } else { "off" }
+}
+
+fn draw_tree(frame: &mut Frame, app: &App, colors: &Colors, area: Rect) {
+    let items: Vec<ListItem> = app
+        .rows
+        .iter()
+        .map(|row| {
+            let marker = match (row.kind, row.has_children) {
+                (NodeKind::Dir, true) if !app.query.is_empty() || app.expanded.contains(&row.path) => "▾ ",
+                (NodeKind::Dir, true) => "▸ ",
+                _ => "  ",
+            };
+            let base = match row.kind {
+                NodeKind::Dir => colors.dir,
+                NodeKind::Symlink => colors.symlink,
+                NodeKind::File => colors.file,
+            };
+            let mut spans = vec![Span::raw("  ".repeat(row.depth)), Span::styled(marker, colors.meta)];
+            for (i, c) in row.name.chars().enumerate() {
+                let style = if row.matched.contains(&i) { colors.highlight } else { base };
+                spans.push(Span::styled(c.to_string(), style));
+            }
+            if row.kind == NodeKind::Dir {
+                spans.push(Span::styled("/", base));
+            }
+            if app.opts.show_sizes {
+                spans.push(Span::styled(format!("  {}", human_size(row.size)), colors.meta));
+            }
+            ListItem::new(Line::from(spans))
+        })
+        .collect();
+
+    let title = format!(" {} ", app.root.path.display());
+    let list = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected((!app.rows.is_empty()).then_some(app.selected));
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+fn draw_preview(frame: &mut Frame, app: &App, colors: &Colors, area: Rect) {
+    let (title, body) = match &app.preview {
+        Some(p) => {
+            let height = area.height.saturating_sub(2) as usize;
+            let width = p.lines.len().min(height).to_string().len();
+            let lines: Vec<Line> = p
+                .lines
+                .iter()
+                .take(height)
+                .enumerate()
+                .map(|(i, l)| {
+                    Line::from(vec![
+                        Span::styled(format!("{:>w$} ", i + 1, w = width), colors.meta),
+                        Span::raw(l.as_str()),
+                    ])
+                })
+                .collect();
+            let name = p.path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+            (format!(" {} — {} ", name, p.info), lines)
+        }
+        None => (" (empty) ".to_string(), Vec::new()),
+    };
+    let para = Paragraph::new(body).block(Block::default().borders(Borders::ALL).title(title));
+    frame.render_widget(para, area);
+}
+
+fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stderr(), LeaveAlternateScreen);
+}
+
+/// Run the browser until the user quits.  `rebuild` walks the directory again
+/// with the given `(show_hidden, respect_gitignore)` toggles.  Returns the
+/// picked path, if any.
+pub fn run<F>(
+    root: Node,
+    opts: TuiOptions,
+    theme: &ThemeColors,
+    use_color: bool,
+    mut rebuild: F,
+) -> Result<Option<PathBuf>, String>
+where
+    F: FnMut(bool, bool) -> Node,
+{
+    let colors = Colors::new(theme, use_color);
+    let mut app = App::new(root, opts);
+
+    enable_raw_mode().map_err(|e| format!("cannot start TUI: {}", e))?;
+    if let Err(e) = execute!(io::stderr(), EnterAlternateScreen) {
+        restore_terminal();
+        return Err(format!("cannot start TUI: {}", e));
+    }
+    // Leave the terminal usable if anything below panics.
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        default_hook(info);
+    }));
+
+    let result = (|| -> io::Result<()> {
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stderr()))?;
+        loop {
+            terminal.draw(|f| draw(f, &mut app, &colors))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match app.handle_key(key) {
+                Action::Continue => {}
+                Action::Rebuild => {
+                    let root = rebuild(app.opts.show_hidden, app.opts.respect_gitignore);
+                    app.set_root(root);
+                }
+                Action::Quit => return Ok(()),
+            }
+        }
+    })();
+
+    restore_terminal();
+    result.map_err(|e| format!("TUI error: {}", e))?;
+    Ok(app.chosen)
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::crossterm::event::{KeyCode, KeyEvent};
+
+    use super::{Action, App, TuiOptions};
+    use crate::walk::{WalkOptions, build_tree};
+
+    fn press(app: &mut App, code: KeyCode) -> Action {
+        app.handle_key(KeyEvent::from(code))
+    }
+
+    fn names(app: &App) -> Vec<&str> {
+        app.rows.iter().map(|r| r.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_navigation_and_search() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("src/walk")).unwrap();
+        std::fs::write(dir.path().join("src/main.rs"), "fn main() {}\n").unwrap();
+        std::fs::write(dir.path().join("src/walk/mod.rs"), "").unwrap();
+        std::fs::write(dir.path().join("README.md"), "# hi\n").unwrap();
+
+        let walk_opts = WalkOptions::default();
+        let opts = TuiOptions {
+            show_hidden: false,
+            respect_gitignore: false,
+            show_sizes: false,
+            picker: true,
+        };
+        let mut app = App::new(build_tree(dir.path(), &walk_opts), opts);
+        assert_eq!(names(&app), ["src", "README.md"]);
+
+        // Expand src, move into it, collapse back to the parent.
+        press(&mut app, KeyCode::Char('l'));
+        assert_eq!(names(&app), ["src", "walk", "main.rs", "README.md"]);
+        press(&mut app, KeyCode::Char('j'));
+        press(&mut app, KeyCode::Char('j'));
+        assert_eq!(app.selected_row().unwrap().name, "main.rs");
+        press(&mut app, KeyCode::Char('h'));
+        assert_eq!(app.selected_row().unwrap().name, "src");
+        press(&mut app, KeyCode::Char('h'));
+        assert_eq!(names(&app), ["src", "README.md"]);
+
+        // Search shows matches with their ancestors, even in collapsed dirs.
+        press(&mut app, KeyCode::Char('/'));
+        for c in "mod".chars() {
+            press(&mut app, KeyCode::Char(c));
+        }
+        press(&mut app, KeyCode::Enter);
+        assert_eq!(names(&app), ["src", "walk", "mod.rs"]);
+        assert_eq!(app.selected_row().unwrap().name, "mod.rs");
+        assert_eq!(app.selected_row().unwrap().matched, [0, 1, 2]);
+
+        // Picking a file quits with its path.
+        assert_eq!(press(&mut app, KeyCode::Enter), Action::Quit);
+        assert_eq!(app.chosen.as_deref(), Some(dir.path().join("src/walk/mod.rs").as_path()));
+    }
+}
@@ -3,11 +3,15 @@
 
 use std::collections::HashSet;
 use std::path::Path;
+use std::sync::{LazyLock, Mutex};
 
 use ignore::WalkBuilder;
 
 use crate::cli::SortKey;
+use crate::content;
 use crate::git::GitStatusMap;
+use crate::lang;
+use crate::search::Matcher;
 use crate::tree::{Node, NodeKind};
 
 /// Parameters that govern what the walker includes/excludes and how it sorts.
@@ -25,15 +29,60 @@ pub struct WalkOptions<'a> {
     pub sort: SortKey,
     pub reverse: bool,
     pub dirs_first: bool,
+    pub count_lines: bool,
+    /// `--text-only`: drop files whose content sniffs as binary.
+    pub text_only: bool,
+    /// `--max-file-size`: don't read the contents of larger files.
+    pub max_file_size: Option<u64>,
+    /// Read the head of files whose name doesn't settle their language
+    /// (shebangs, modelines, `.h`/`.m`).  Always on with `-e`.
+    pub sniff_languages: bool,
     pub git_status: Option<&'a GitStatusMap>,
+    /// `--find`: keep only matching entries and their ancestors.
+    pub find: Option<&'a Matcher>,
+}
+
+impl Default for WalkOptions<'_> {
+    /// Everything but hidden entries, unfiltered, sorted by name with
+    /// directories first.
+    fn default() -> Self {
+        static NO_NAMES: LazyLock<HashSet<String>> = LazyLock::new(HashSet::new);
+        WalkOptions {
+            max_depth: None,
+            show_hidden: false,
+            respect_gitignore: false,
+            ignore_names: &NO_NAMES,
+            include_globs: &[],
+            exclude_globs: &[],
+            extension_filter: None,
+            dirs_only: false,
+            files_only: false,
+            prune: false,
+            sort: SortKey::Name,
+            reverse: false,
+            dirs_first: true,
+            count_lines: false,
+            text_only: false,
+            max_file_size: None,
+            sniff_languages: false,
+            git_status: None,
+            find: None,
+        }
+    }
 }
 
 /// Build a `Node` tree rooted at `root`.
 pub fn build_tree(root: &Path, opts: &WalkOptions) -> Node {
     let mut root_node = build_node(root, opts, 0);
+    if opts.count_lines || opts.sort == SortKey::Lines {
+        count_tree_lines(&mut root_node, opts);
+    }
     if opts.prune {
         prune_empty_dirs(&mut root_node);
     }
+    if opts.sort == SortKey::Lines {
+        sort_by_lines(&mut root_node, opts);
+    }
     root_node
 }
 
@@ -60,14 +109,18 @@ fn build_node(path: &Path, opts: &WalkOptions, depth: usize) -> Node {
         .unwrap_or(0);
 
     #[cfg(unix)]
-    let is_exec = meta.as_ref()
+    let mode = meta.as_ref()
         .map(|m| {
             use std::os::unix::fs::PermissionsExt;
-            m.permissions().mode() & 0o111 != 0
+            m.permissions().mode() & 0o7777
         })
-        .unwrap_or(false);
+        .unwrap_or(0);
     #[cfg(not(unix))]
-    let is_exec = false;
+    let mode = 0;
+    let is_exec = mode & 0o111 != 0;
+
+    // Line counts are filled in afterwards by `count_tree_lines`.
+    let binary = opts.text_only && kind == NodeKind::File && content::is_binary_file(path);
 
     let git_xy = opts.git_status.and_then(|map| map.get(path).copied());
 
@@ -78,14 +131,20 @@ fn build_node(path: &Path, opts: &WalkOptions, depth: usize) -> Node {
         size,
         mtime,
         is_exec,
+        mode,
+        lines: None,
+        hash: None,
+        matches: None,
+        binary,
         git_xy,
+        lang: None,
     };
 
     if kind == NodeKind::Dir {
-        if let Some(max) = opts.max_depth {
-            if depth >= max {
-                return node;
-            }
+        if let Some(max) = opts.max_depth
+            && depth >= max
+        {
+            return node;
         }
         node.children = read_children(path, opts, depth + 1);
     }
@@ -99,69 +158,20 @@ fn read_children(dir: &Path, opts: &WalkOptions, depth: usize) -> Vec<Node> {
         Err(_) => return Vec::new(),
     };
 
+    let entries: Vec<std::fs::DirEntry> = entries.filter_map(|res| res.ok()).collect();
+    // Decides whether `.h` files here are C++, for every file at once.
+    let cpp_siblings = entries
+        .iter()
+        .any(|e| lang::is_cpp_source(&e.file_name().to_string_lossy()));
+    let sniff = opts.sniff_languages || opts.extension_filter.is_some();
+
     let mut children: Vec<Node> = entries
-        .filter_map(|res| res.ok())
+        .into_iter()
         .filter(|entry| {
             let name_os = entry.file_name();
             let name = name_os.to_string_lossy();
-            let path = entry.path();
-
-            // Hidden files
-            if !opts.show_hidden && name.starts_with('.') {
-                return false;
-            }
-
             let is_dir = entry.file_type().map(|t| t.is_dir()).unwrap_or(false);
-
-            // Ignored names
-            if opts.ignore_names.contains(name.as_ref()) {
-                return false;
-            }
-
-            // Gitignore — delegate to ignore crate for files inside a walk
-            // (We handle this via WalkBuilder for the root; for children we do
-            //  a simple inline check using the ignore crate's matcher.)
-            // Actually we build per-entry below using ignore crate.
-
-            // dirs_only / files_only
-            if opts.dirs_only && !is_dir {
-                return false;
-            }
-            if opts.files_only && is_dir {
-                return false;
-            }
-
-            // Extension filter (files only)
-            if !is_dir {
-                if let Some(ext) = opts.extension_filter {
-                    let file_ext = path.extension().and_then(|e| e.to_str()).unwrap_or("");
-                    if file_ext != ext {
-                        return false;
-                    }
-                }
-            }
-
-            // Include globs (files only, skip dirs so we can recurse)
-            if !opts.include_globs.is_empty() && !is_dir {
-                let matched = opts.include_globs.iter().any(|pat| {
-                    glob_match(pat, name.as_ref())
-                });
-                if !matched {
-                    return false;
-                }
-            }
-
-            // Exclude globs
-            if !opts.exclude_globs.is_empty() {
-                let excluded = opts.exclude_globs.iter().any(|pat| {
-                    glob_match(pat, name.as_ref())
-                });
-                if excluded {
-                    return false;
-                }
-            }
-
-            true
+            passes_filters(&name, is_dir, opts)
         })
         .filter(|entry| {
             // Gitignore via ignore crate's standalone matcher
@@ -171,13 +181,218 @@ fn read_children(dir: &Path, opts: &WalkOptions, depth: usize) -> Vec<Node> {
                 true
             }
         })
-        .map(|entry| build_node(&entry.path(), opts, depth))
+        .map(|entry| {
+            let mut node = build_node(&entry.path(), opts, depth);
+            if node.kind == NodeKind::File {
+                node.lang = lang::detect_in_dir(&node.path, cpp_siblings, sniff);
+            }
+            node
+        })
+        .filter(|node| passes_extension_filter(node, opts))
+        .filter(|node| !(opts.text_only && node.binary))
+        .filter(|node| keeps_find_match(node, opts))
         .collect();
 
     sort_children(&mut children, opts);
     children
 }
 
+/// Whether `path` (somewhere below `root`) would be hidden by the filters,
+/// checking every path component.  Used to drop irrelevant watch events.
+pub fn is_filtered_out(root: &Path, path: &Path, opts: &WalkOptions) -> bool {
+    let Ok(rel) = path.strip_prefix(root) else {
+        return true;
+    };
+    let components: Vec<_> = rel.components().collect();
+    for (i, comp) in components.iter().enumerate() {
+        let name = comp.as_os_str().to_string_lossy();
+        let is_last = i + 1 == components.len();
+        let is_dir = !is_last || path.is_dir();
+        if !passes_filters(&name, is_dir, opts) {
+            return true;
+        }
+    }
+    if let Some(filter) = opts.extension_filter
+        && !path.is_dir()
+        && !lang::matches_filter(path, lang::detect_path(path), filter)
+    {
+        return true;
+    }
+    opts.respect_gitignore && path.exists() && is_gitignored(path)
+}
+
+/// Fill `Node::lines` (or `Node::binary`) for every file below `node`.
+/// Files are streamed rather than loaded, and spread over one worker per
+/// core; files above `max_file_size` are left uncounted.
+fn count_tree_lines(node: &mut Node, opts: &WalkOptions) {
+    let mut files = Vec::new();
+    collect_files_mut(node, &mut files);
+    let workers = std::thread::available_parallelism()
+        .map_or(1, |n| n.get())
+        .min(files.len());
+    // A shared queue rather than fixed chunks, so one huge file doesn't hold
+    // up a whole chunk of small ones.
+    let queue = Mutex::new(files.into_iter());
+    std::thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| {
+                loop {
+                    let Some(file) = queue.lock().unwrap().next() else {
+                        break;
+                    };
+                    if opts.max_file_size.is_some_and(|max| file.size > max) {
+                        continue;
+                    }
+                    match content::count_lines(&file.path) {
+                        Ok(Some(n)) => file.lines = Some(n),
+                        Ok(None) => file.binary = true,
+                        Err(_) => {}
+                    }
+                }
+            });
+        }
+    });
+}
+
+fn collect_files_mut<'a>(node: &'a mut Node, files: &mut Vec<&'a mut Node>) {
+    if node.kind == NodeKind::File {
+        files.push(node);
+        return;
+    }
+    for child in &mut node.children {
+        collect_files_mut(child, files);
+    }
+}
+
+/// Re-read the directory `dir` inside an existing tree, replacing its node.
+/// Only that subtree is re-pruned and re-sorted; with `-s lines` its
+/// ancestors' entries are re-ordered too, since their totals changed.
+/// Returns `false` if `dir` is not part of the tree.
+pub fn rebuild_subtree(root: &mut Node, dir: &Path, opts: &WalkOptions) -> bool {
+    let depth = match dir.strip_prefix(&root.path) {
+        Ok(rel) => rel.components().count(),
+        Err(_) => return false,
+    };
+    let Some(node) = root.find_mut(dir) else {
+        return false;
+    };
+    *node = build_node(dir, opts, depth);
+    if opts.count_lines || opts.sort == SortKey::Lines {
+        count_tree_lines(node, opts);
+    }
+    if opts.prune {
+        prune_empty_dirs(node);
+    }
+    if opts.sort == SortKey::Lines {
+        sort_by_lines(node, opts);
+    }
+
+    // Back up to the root: drop directories left empty, re-order by the new totals.
+    let mut emptied = opts.prune && node.children.is_empty();
+    let mut child = dir;
+    for ancestor in dir.ancestors().skip(1).take(depth) {
+        let Some(parent) = root.find_mut(ancestor) else {
+            break;
+        };
+        if emptied {
+            parent.children.retain(|c| c.path != child);
+        }
+        if opts.sort == SortKey::Lines {
+            let totals = parent.children.iter().map(|c| c.total_lines().unwrap_or(0)).collect();
+            order_by_lines(parent, totals, opts);
+        }
+        emptied = opts.prune && parent.children.is_empty();
+        child = ancestor;
+    }
+    true
+}
+
+/// `-e`: files with the extension, or detected as the language it names.
+/// Separate from `passes_filters` since it needs the detected language.
+fn passes_extension_filter(node: &Node, opts: &WalkOptions) -> bool {
+    match opts.extension_filter {
+        Some(filter) if node.kind != NodeKind::Dir => {
+            lang::matches_filter(&node.path, node.lang, filter)
+        }
+        _ => true,
+    }
+}
+
+/// Name-based filters shared by the live walker and `filter_tree`.
+fn passes_filters(name: &str, is_dir: bool, opts: &WalkOptions) -> bool {
+    // Hidden files
+    if !opts.show_hidden && name.starts_with('.') {
+        return false;
+    }
+
+    // Ignored names
+    if opts.ignore_names.contains(name) {
+        return false;
+    }
+
+    // dirs_only / files_only
+    if opts.dirs_only && !is_dir {
+        return false;
+    }
+    if opts.files_only && is_dir {
+        return false;
+    }
+
+    // Include globs (files only, skip dirs so we can recurse)
+    if !opts.include_globs.is_empty() && !is_dir {
+        let matched = opts.include_globs.iter().any(|pat| glob_match(pat, name));
+        if !matched {
+            return false;
+        }
+    }
+
+    // Exclude globs
+    if opts.exclude_globs.iter().any(|pat| glob_match(pat, name)) {
+        return false;
+    }
+
+    true
+}
+
+/// Apply depth, name filters, sorting and pruning to an already-built tree
+/// (e.g. one re-imported from a JSON snapshot) without touching the filesystem.
+/// Gitignore rules can't be evaluated offline and are skipped.
+pub fn filter_tree(mut root: Node, opts: &WalkOptions) -> Node {
+    filter_children(&mut root, opts, 0);
+    if opts.prune {
+        prune_empty_dirs(&mut root);
+    }
+    if opts.sort == SortKey::Lines {
+        sort_by_lines(&mut root, opts);
+    }
+    root
+}
+
+fn filter_children(node: &mut Node, opts: &WalkOptions, depth: usize) {
+    if opts.max_depth.is_some_and(|max| depth >= max) {
+        node.children.clear();
+        return;
+    }
+    node.children.retain(|c| {
+        let is_dir = c.kind == NodeKind::Dir;
+        passes_filters(c.name(), is_dir, opts)
+            && passes_extension_filter(c, opts)
+            && !(opts.text_only && c.binary)
+    });
+    for child in &mut node.children {
+        filter_children(child, opts, depth + 1);
+    }
+    node.children.retain(|c| keeps_find_match(c, opts));
+    sort_children(&mut node.children, opts);
+}
+
+/// With `--find`, an entry stays if its name matches or it still has children
+/// (which, being filtered bottom-up, lead to a match).
+fn keeps_find_match(node: &Node, opts: &WalkOptions) -> bool {
+    opts.find
+        .is_none_or(|m| !node.children.is_empty() || m.find(node.name()).is_some())
+}
+
 /// Minimal gitignore check using the `ignore` crate's WalkBuilder on a single path.
 fn is_gitignored(path: &Path) -> bool {
     // Build a one-shot walker; if it yields the path, it's not ignored.
@@ -209,24 +424,11 @@ fn is_gitignored(path: &Path) -> bool {
     true
 }
 
-fn sort_children(children: &mut Vec<Node>, opts: &WalkOptions) {
-    let dirs_first = opts.dirs_first && !opts.dirs_only && !opts.files_only;
-
+fn sort_children(children: &mut [Node], opts: &WalkOptions) {
     children.sort_by(|a, b| {
-        // Dirs first?
-        if dirs_first {
-            let a_dir = a.kind == NodeKind::Dir;
-            let b_dir = b.kind == NodeKind::Dir;
-            if a_dir && !b_dir {
-                return std::cmp::Ordering::Less;
-            }
-            if !a_dir && b_dir {
-                return std::cmp::Ordering::Greater;
-            }
-        }
-
         let ord = match opts.sort {
-            SortKey::Name | SortKey::None => a.name().cmp(b.name()),
+            // Put in order by `sort_by_lines` once lines are counted.
+            SortKey::Name | SortKey::None | SortKey::Lines => a.name().cmp(b.name()),
             SortKey::Size => a.size.cmp(&b.size),
             SortKey::Time => a.mtime.cmp(&b.mtime),
             SortKey::Ext => {
@@ -235,11 +437,46 @@ fn sort_children(children: &mut Vec<Node>, opts: &WalkOptions) {
                 ea.cmp(eb).then(a.name().cmp(b.name()))
             }
         };
-
-        if opts.reverse { ord.reverse() } else { ord }
+        order(a, b, ord, opts)
     });
 }
 
+/// `ord` with directories first (unless disabled) and `--reverse` applied.
+fn order(a: &Node, b: &Node, ord: std::cmp::Ordering, opts: &WalkOptions) -> std::cmp::Ordering {
+    let dirs_first = opts.dirs_first && !opts.dirs_only && !opts.files_only;
+    if dirs_first {
+        let a_dir = a.kind == NodeKind::Dir;
+        let b_dir = b.kind == NodeKind::Dir;
+        if a_dir && !b_dir {
+            return std::cmp::Ordering::Less;
+        }
+        if !a_dir && b_dir {
+            return std::cmp::Ordering::Greater;
+        }
+    }
+    if opts.reverse { ord.reverse() } else { ord }
+}
+
+/// `-s lines`: order every directory's entries by line count, and return
+/// the total below `node`.  Uses the counts already on the tree
+/// (`Node::lines`); uncounted and binary files weigh nothing.
+fn sort_by_lines(node: &mut Node, opts: &WalkOptions) -> usize {
+    if node.kind != NodeKind::Dir {
+        return node.lines.unwrap_or(0);
+    }
+    let totals = node.children.iter_mut().map(|c| sort_by_lines(c, opts)).collect();
+    order_by_lines(node, totals, opts)
+}
+
+/// Order `node`'s entries by `totals` (one per entry), and return their sum.
+fn order_by_lines(node: &mut Node, totals: Vec<usize>, opts: &WalkOptions) -> usize {
+    let mut keyed: Vec<(usize, Node)> = totals.into_iter().zip(node.children.drain(..)).collect();
+    keyed.sort_by(|(ka, a), (kb, b)| order(a, b, ka.cmp(kb).then(a.name().cmp(b.name())), opts));
+    let total = keyed.iter().map(|(k, _)| k).sum();
+    node.children = keyed.into_iter().map(|(_, child)| child).collect();
+    total
+}
+
 /// Remove directories that have no visible children (after filtering).
 fn prune_empty_dirs(node: &mut Node) {
     if node.kind != NodeKind::Dir {
@@ -280,7 +517,9 @@ fn glob_match_inner(pat: &[char], txt: &[char]) -> bool {
 
 #[cfg(test)]
 mod tests {
-    use super::glob_match;
+    use super::{WalkOptions, build_tree, glob_match, rebuild_subtree};
+    use crate::cli::SortKey;
+    use crate::tree::Node;
 
     #[test]
     fn test_glob() {
@@ -291,4 +530,50 @@ mod tests {
         assert!(glob_match("f?o", "foo"));
         assert!(!glob_match("f?o", "fo"));
     }
+
+    #[test]
+    fn test_sort_by_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir(root.join("lib")).unwrap();
+        std::fs::write(root.join("lib/x.rs"), "fn x() {}\nfn y() {}\n").unwrap();
+        std::fs::write(root.join("lib/y.rs"), "fn z() {}\n").unwrap();
+        std::fs::write(root.join("big.rs"), "// a\n// b\n// c\n// d\nfn b() {}\n").unwrap();
+        std::fs::write(root.join("small.py"), "x = 1\n").unwrap();
+
+        let opts = WalkOptions {
+            sort: SortKey::Lines,
+            reverse: true,
+            dirs_first: false,
+            ..WalkOptions::default()
+        };
+        let mut tree = build_tree(root, &opts);
+        let names = |n: &Node| n.children.iter().map(|c| c.name().to_string()).collect::<Vec<_>>();
+        assert_eq!(names(&tree), ["big.rs", "lib", "small.py"]);
+        assert_eq!(names(&tree.children[1]), ["x.rs", "y.rs"]);
+
+        // A change inside lib/ re-orders lib/ and, by its new total, the root.
+        std::fs::write(root.join("lib/z.rs"), "fn w() {}\n".repeat(4)).unwrap();
+        assert!(rebuild_subtree(&mut tree, &root.join("lib"), &opts));
+        assert_eq!(names(&tree), ["lib", "big.rs", "small.py"]);
+        assert_eq!(names(&tree.children[0]), ["z.rs", "x.rs", "y.rs"]);
+    }
+
+    #[test]
+    fn test_rebuild_subtree_prunes_upwards() {
+        let dir = tempfile::tempdir().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("a/b")).unwrap();
+        std::fs::write(root.join("a/b/f.txt"), "f").unwrap();
+        std::fs::write(root.join("keep.txt"), "k").unwrap();
+
+        let opts = WalkOptions { prune: true, ..WalkOptions::default() };
+        let mut tree = build_tree(root, &opts);
+        assert_eq!(tree.children.len(), 2);
+
+        std::fs::remove_file(root.join("a/b/f.txt")).unwrap();
+        assert!(rebuild_subtree(&mut tree, &root.join("a/b"), &opts));
+        let names: Vec<&str> = tree.children.iter().map(|c| c.name()).collect();
+        assert_eq!(names, ["keep.txt"]);
+    }
 }
@@ -0,0 +1,222 @@
+//! `--watch`: keep the tree on screen and redraw it when the filesystem changes.
+//!
+//! Events from `notify` are debounced into batches; each batch rebuilds only
+//! the directories whose listings changed, and the touched entries stay
+//! highlighted for a while.
+
+use std::collections::{HashMap, HashSet};
+use std::io::Write as _;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use notify::{Event, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::tree::Node;
+use crate::walk::{self, WalkOptions};
+
+/// Quiet period that ends a batch of events.
+pub const DEBOUNCE: Duration = Duration::from_millis(200);
+/// How long a changed entry stays highlighted.
+pub const HIGHLIGHT_FOR: Duration = Duration::from_secs(10);
+
+/// A recursive watch on one directory.
+pub struct TreeWatcher {
+    // Dropping the watcher stops event delivery, so keep it alive.
+    _watcher: RecommendedWatcher,
+    rx: Receiver<notify::Result<Event>>,
+}
+
+impl TreeWatcher {
+    pub fn new(root: &Path) -> notify::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(move |res| {
+            let _ = tx.send(res);
+        })?;
+        watcher.watch(root, RecursiveMode::Recursive)?;
+        Ok(Self { _watcher: watcher, rx })
+    }
+
+    /// Wait up to `timeout` for an event, then keep collecting until the
+    /// stream has been quiet for `debounce`.  Returns `None` on timeout.
+    pub fn next_batch(&self, timeout: Duration, debounce: Duration) -> Option<HashSet<PathBuf>> {
+        let mut paths = HashSet::new();
+        match self.rx.recv_timeout(timeout) {
+            Ok(res) => add_event_paths(res, &mut paths),
+            Err(RecvTimeoutError::Timeout | RecvTimeoutError::Disconnected) => return None,
+        }
+        while let Ok(res) = self.rx.recv_timeout(debounce) {
+            add_event_paths(res, &mut paths);
+        }
+        Some(paths)
+    }
+}
+
+fn add_event_paths(res: notify::Result<Event>, paths: &mut HashSet<PathBuf>) {
+    if let Ok(event) = res
+        && !event.kind.is_access()
+    {
+        paths.extend(event.paths);
+    }
+}
+
+/// Rebuild the parts of `root` affected by `changed` paths.  Returns the
+/// entries to highlight: changed paths still in the tree, or the parent
+/// directory of removed ones.
+pub fn apply_changes(
+    root: &mut Node,
+    changed: &HashSet<PathBuf>,
+    opts: &WalkOptions,
+) -> HashSet<PathBuf> {
+    let root_path = root.path.clone();
+    let relevant: Vec<&PathBuf> = changed
+        .iter()
+        .filter(|p| **p != root_path && !walk::is_filtered_out(&root_path, p, opts))
+        .collect();
+
+    // The directory whose listing changed is the parent; climb until we hit
+    // one that is already part of the tree (new nested dirs aren't yet).
+    let mut dirs: Vec<PathBuf> = Vec::new();
+    for path in &relevant {
+        let mut dir = path.parent().unwrap_or(&root_path).to_path_buf();
+        while dir != root_path && root.find_mut(&dir).is_none() {
+            match dir.parent() {
+                Some(p) => dir = p.to_path_buf(),
+                None => break,
+            }
+        }
+        if !dirs.contains(&dir) {
+            dirs.push(dir);
+        }
+    }
+    // Rebuilding a directory covers everything beneath it.
+    let outer: Vec<PathBuf> = dirs
+        .iter()
+        .filter(|d| !dirs.iter().any(|o| o != *d && d.starts_with(o)))
+        .cloned()
+        .collect();
+    for dir in &outer {
+        walk::rebuild_subtree(root, dir, opts);
+    }
+
+    let mut highlight = HashSet::new();
+    for path in relevant {
+        if root.find_mut(path).is_some() {
+            highlight.insert(path.clone());
+        } else if let Some(parent) = path.parent()
+            && root.find_mut(parent).is_some()
+        {
+            highlight.insert(parent.to_path_buf());
+        }
+    }
+    highlight
+}
+
+/// Run the watch loop until interrupted.  `render` turns the current tree and
+/// the set of highlighted paths into the text to show.
+pub fn run<F>(mut root: Node, opts: &WalkOptions, mut render: F) -> Result<(), String>
+where
+    F: FnMut(&Node, &HashSet<PathBuf>) -> String,
+{
+    let watcher = TreeWatcher::new(&root.path)
+        .map_err(|e| format!("cannot watch '{}': {}", root.path.display(), e))?;
+    let mut recent: HashMap<PathBuf, Instant> = HashMap::new();
+    let mut last_change: Option<Instant> = None;
+
+    redraw(&render(&root, &HashSet::new()), &root.path, last_change);
+    loop {
+        let batch = watcher.next_batch(Duration::from_secs(1), DEBOUNCE);
+        let now = Instant::now();
+        let mut dirty = false;
+
+        if let Some(changed) = batch {
+            let touched = apply_changes(&mut root, &changed, opts);
+            if !touched.is_empty() {
+                for path in touched {
+                    recent.insert(path, now);
+                }
+                last_change = Some(now);
+                dirty = true;
+            }
+        }
+
+        let before = recent.len();
+        recent.retain(|_, at| now.duration_since(*at) < HIGHLIGHT_FOR);
+        dirty |= recent.len() != before;
+
+        if dirty {
+            let highlight: HashSet<PathBuf> = recent.keys().cloned().collect();
+            redraw(&render(&root, &highlight), &root.path, last_change);
+        }
+    }
+}
+
+/// Clear the screen and draw `body` plus a status line.
+fn redraw(body: &str, root: &Path, last_change: Option<Instant>) {
+    let status = match last_change {
+        Some(at) => format!("last change {}s ago", at.elapsed().as_secs()),
+        None => "no changes yet".into(),
+    };
+    let mut stdout = std::io::stdout().lock();
+    let _ = write!(
+        stdout,
+        "\x1b[H\x1b[2J{}\nWatching {} — {} (Ctrl-C to quit)\n",
+        body,
+        root.display(),
+        status
+    );
+    let _ = stdout.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    use super::{TreeWatcher, apply_changes};
+    use crate::walk::{WalkOptions, build_tree};
+
+    #[test]
+    fn test_apply_changes() {
+        let dir = tempfile::tempdir().unwrap();
+        let root_path = dir.path().to_path_buf();
+        std::fs::create_dir(root_path.join("src")).unwrap();
+        std::fs::write(root_path.join("src/a.rs"), "fn a() {}\n").unwrap();
+
+        let ignore: HashSet<String> = ["target".to_string()].into();
+        let opts = WalkOptions { ignore_names: &ignore, ..WalkOptions::default() };
+        let mut root = build_tree(&root_path, &opts);
+
+        // New nested directory, a removal, and noise in an ignored directory.
+        std::fs::create_dir_all(root_path.join("src/deep/er")).unwrap();
+        std::fs::write(root_path.join("src/deep/er/b.rs"), "").unwrap();
+        std::fs::remove_file(root_path.join("src/a.rs")).unwrap();
+        std::fs::create_dir(root_path.join("target")).unwrap();
+        let changed: HashSet<_> = [
+            root_path.join("src/deep/er/b.rs"),
+            root_path.join("src/a.rs"),
+            root_path.join("target/junk"),
+        ]
+        .into();
+
+        let highlight = apply_changes(&mut root, &changed, &opts);
+        assert!(highlight.contains(&root_path.join("src/deep/er/b.rs")));
+        assert!(highlight.contains(&root_path.join("src")));
+        assert!(root.find_mut(&root_path.join("src/deep/er/b.rs")).is_some());
+        assert!(root.find_mut(&root_path.join("src/a.rs")).is_none());
+        assert!(root.find_mut(&root_path.join("target")).is_none());
+    }
+
+    #[test]
+    fn test_watcher_batches_events() {
+        let dir = tempfile::tempdir().unwrap();
+        let watcher = TreeWatcher::new(dir.path()).unwrap();
+        let file = dir.path().join("new.txt");
+        std::fs::write(&file, "hello").unwrap();
+
+        let batch = watcher
+            .next_batch(Duration::from_secs(5), Duration::from_millis(100))
+            .expect("no events received");
+        assert!(batch.iter().any(|p| p.ends_with("new.txt")));
+    }
+}